@@ -14,7 +14,12 @@ fn main() {
 	let compiler = env::var("CC").unwrap_or("gcc".to_string());
 	let executable = dst_dir.join("rust-constants");
 
-	let c_src = src_dir.join("src/constants.c");
+	// Each platform backend has its own set of constants
+	let c_src = match env::var("CARGO_CFG_TARGET_OS").unwrap().as_str() {
+		"macos" => src_dir.join("src/constants_macos.c"),
+		"freebsd" => src_dir.join("src/constants_freebsd.c"),
+		_ => src_dir.join("src/constants.c"),
+	};
 	let rust_dst = dst_dir.join("constants.rs");
 
 	// Compile C code
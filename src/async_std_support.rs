@@ -0,0 +1,78 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use async_io::Async;
+use futures_io::{AsyncRead, AsyncWrite};
+use error::TunTapError;
+use tuntap::{TunTap, TunTapType};
+
+/// A TunTap registered with the async-io reactor used by async-std.
+///
+/// Configuration methods are still reachable through `get_ref`.
+pub struct AsyncStdTunTap {
+    inner: Async<TunTap>,
+}
+
+impl AsyncStdTunTap {
+    pub fn new(typ: TunTapType, name: &str) -> Result<AsyncStdTunTap, TunTapError> {
+        AsyncStdTunTap::from_tuntap(TunTap::new(typ, name)?)
+    }
+
+    /// Switches `tuntap` into non-blocking mode and registers it with the
+    /// async-io reactor.
+    pub fn from_tuntap(tuntap: TunTap) -> Result<AsyncStdTunTap, TunTapError> {
+        tuntap.set_nonblocking(true)?;
+        Ok(AsyncStdTunTap {
+            inner: Async::new(tuntap)?,
+        })
+    }
+
+    pub fn get_ref(&self) -> &TunTap {
+        self.inner.get_ref()
+    }
+
+    pub fn into_inner(self) -> Result<TunTap, TunTapError> {
+        Ok(self.inner.into_inner()?)
+    }
+}
+
+// Both go through the file directly, TunTap::read would wait in poll()
+impl AsyncRead for AsyncStdTunTap {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8])
+                 -> Poll<io::Result<usize>> {
+        loop {
+            match self.inner.get_ref().read_fd(buf) {
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                res => return Poll::Ready(res),
+            }
+            match self.inner.poll_readable(cx) {
+                Poll::Ready(res) => res?,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for AsyncStdTunTap {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8])
+                  -> Poll<io::Result<usize>> {
+        loop {
+            match self.inner.get_ref().write_fd(buf) {
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                res => return Poll::Ready(res),
+            }
+            match self.inner.poll_writable(cx) {
+                Poll::Ready(res) => res?,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,77 @@
+/// A reusable set of fixed-size packet buffers for `TunTap::read_into_batch`.
+///
+/// The slots are carved out of a single allocation made up front, so a
+/// forwarding loop can read batch after batch without allocating.
+#[derive(Debug, Clone)]
+pub struct PacketBatch {
+    storage: Vec<u8>,
+    slots: usize,
+    slot_size: usize,
+    lens: Vec<usize>,
+}
+
+impl PacketBatch {
+    /// Creates a batch of `slots` buffers of `slot_size` bytes each. Packets
+    /// longer than `slot_size` are truncated by the read, so size the slots
+    /// with `TunTap::recommended_buffer_size`.
+    ///
+    /// Panics if the batch would be larger than `usize::MAX` bytes.
+    pub fn new(slots: usize, slot_size: usize) -> PacketBatch {
+        let size = slots.checked_mul(slot_size).expect("PacketBatch size overflows usize");
+        PacketBatch {
+            storage: vec![0; size],
+            slots,
+            slot_size,
+            lens: Vec::with_capacity(slots),
+        }
+    }
+
+    /// Number of slots, the most packets a single read can fill.
+    pub fn capacity(&self) -> usize {
+        self.slots
+    }
+
+    pub fn slot_size(&self) -> usize {
+        self.slot_size
+    }
+
+    /// Number of packets filled by the last read.
+    pub fn len(&self) -> usize {
+        self.lens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lens.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.lens.clear();
+    }
+
+    /// Returns the `index`th packet of the last read.
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        self.lens.get(index).map(|&len| {
+            let start = index * self.slot_size;
+            &self.storage[start..start + len]
+        })
+    }
+
+    /// Iterates over the packets of the last read in the order they arrived.
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        (0..self.lens.len()).filter_map(move |index| self.get(index))
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.lens.len() == self.capacity()
+    }
+
+    // The next unfilled slot, to be committed with `push`
+    pub(crate) fn next_slot(&mut self) -> &mut [u8] {
+        let start = self.lens.len() * self.slot_size;
+        &mut self.storage[start..start + self.slot_size]
+    }
+
+    pub(crate) fn push(&mut self, len: usize) {
+        self.lens.push(len);
+    }
+}
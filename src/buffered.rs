@@ -0,0 +1,82 @@
+use std::fmt;
+use std::io::{self, Write};
+use tuntap::TunTap;
+
+/// A TunTap that reads each packet into a buffer it owns, allocated once
+/// and reused for every read. Only reads are buffered: `write` reaches the
+/// kernel before it returns, as with a plain TunTap.
+pub struct BufferedTunTap {
+    inner: TunTap,
+    buffer: Vec<u8>,
+}
+
+impl BufferedTunTap {
+    /// Wraps `inner` with a buffer of `TunTap::recommended_buffer_size`
+    /// bytes, which accounts for offloads and any headers. Enable offloads
+    /// before wrapping, as the buffer doesn't grow afterwards.
+    pub fn new(inner: TunTap) -> BufferedTunTap {
+        let capacity = inner.recommended_buffer_size();
+        BufferedTunTap::with_capacity(inner, capacity)
+    }
+
+    pub fn with_capacity(inner: TunTap, capacity: usize) -> BufferedTunTap {
+        BufferedTunTap {
+            inner,
+            buffer: vec![0; capacity],
+        }
+    }
+
+    /// The largest packet `next_packet` can return, headers included.
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Reads the next packet, borrowed until the following call. A packet
+    /// longer than `capacity` fails like `TunTap::read_exact_frame` instead
+    /// of being returned truncated.
+    pub fn next_packet(&mut self) -> io::Result<&[u8]> {
+        let len = self.inner.read_exact_frame(&mut self.buffer)?;
+        Ok(&self.buffer[..len])
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.inner.write(data)
+    }
+
+    /// See `TunTap::flush`, there's never anything queued.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Gives access to the configuration methods of the device.
+    pub fn get_ref(&self) -> &TunTap {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut TunTap {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> TunTap {
+        self.inner
+    }
+}
+
+impl fmt::Debug for BufferedTunTap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BufferedTunTap")
+            .field("inner", &self.inner)
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+impl Write for BufferedTunTap {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write_packet(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,316 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use libc::EPERM;
+use c_interop::*;
+use error::TunTapError;
+use tuntap::{OpenMode, TunTap, TunTapType, DEVICE_PATH, SYS_CLASS_NET};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Creates and configures a TunTap in one step.
+///
+/// ```no_run
+/// use tuntap::{TunTapBuilder, Tun};
+///
+/// let tun = TunTapBuilder::new()
+///     .name("tun0")
+///     .typ(Tun)
+///     .mtu(1400)
+///     .ipv4("10.0.0.1".parse().unwrap(), 24)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TunTapBuilder {
+    device_path: PathBuf,
+    name: String,
+    typ: TunTapType,
+    packet_info: bool,
+    multi_queue: bool,
+    vnet_hdr: bool,
+    mtu: Option<u32>,
+    ipv4: Option<(Ipv4Addr, u8)>,
+    mac: Option<[u8; 6]>,
+    persistent: bool,
+    exclusive: bool,
+    open_mode: OpenMode,
+    cloexec: bool,
+    noarp: Option<bool>,
+    bring_up: bool,
+    best_effort_up: bool,
+}
+
+impl Default for TunTapBuilder {
+    fn default() -> TunTapBuilder {
+        TunTapBuilder::new()
+    }
+}
+
+impl TunTapBuilder {
+    pub fn new() -> TunTapBuilder {
+        TunTapBuilder {
+            device_path: PathBuf::from(DEVICE_PATH),
+            name: String::new(),
+            typ: TunTapType::Tun,
+            packet_info: false,
+            multi_queue: false,
+            vnet_hdr: false,
+            mtu: None,
+            ipv4: None,
+            mac: None,
+            persistent: false,
+            exclusive: false,
+            open_mode: OpenMode::ReadWrite,
+            cloexec: true,
+            noarp: None,
+            bring_up: true,
+            best_effort_up: false,
+        }
+    }
+
+    /// Opens the tun device node at `path` instead of `/dev/net/tun`.
+    pub fn device_path<P: AsRef<Path>>(&mut self, path: P) -> &mut TunTapBuilder {
+        self.device_path = path.as_ref().to_path_buf();
+        self
+    }
+
+    pub fn name(&mut self, name: &str) -> &mut TunTapBuilder {
+        self.name = name.to_string();
+        self
+    }
+
+    pub fn typ(&mut self, typ: TunTapType) -> &mut TunTapBuilder {
+        self.typ = typ;
+        self
+    }
+
+    /// Keeps the packet information header, see `TunTap::new_with_packet_info`.
+    pub fn packet_info(&mut self, packet_info: bool) -> &mut TunTapBuilder {
+        self.packet_info = packet_info;
+        self
+    }
+
+    pub fn multi_queue(&mut self, multi_queue: bool) -> &mut TunTapBuilder {
+        self.multi_queue = multi_queue;
+        self
+    }
+
+    /// Prepends a virtio-net header to every packet, see `TunTap::read_with_vnet_hdr`.
+    /// Required for checksum and segmentation offloads.
+    pub fn vnet_hdr(&mut self, vnet_hdr: bool) -> &mut TunTapBuilder {
+        self.vnet_hdr = vnet_hdr;
+        self
+    }
+
+    pub fn mtu(&mut self, mtu: u32) -> &mut TunTapBuilder {
+        self.mtu = Some(mtu);
+        self
+    }
+
+    pub fn ipv4(&mut self, addr: Ipv4Addr, prefix_len: u8) -> &mut TunTapBuilder {
+        self.ipv4 = Some((addr, prefix_len));
+        self
+    }
+
+    pub fn mac(&mut self, mac: [u8; 6]) -> &mut TunTapBuilder {
+        self.mac = Some(mac);
+        self
+    }
+
+    pub fn persistent(&mut self, persistent: bool) -> &mut TunTapBuilder {
+        self.persistent = persistent;
+        self
+    }
+
+    /// Fails with EBUSY if a device with the same name already exists,
+    /// instead of attaching to it.
+    pub fn exclusive(&mut self, exclusive: bool) -> &mut TunTapBuilder {
+        self.exclusive = exclusive;
+        self
+    }
+
+    /// Opens the device only for reading or only for writing packets.
+    /// Defaults to `OpenMode::ReadWrite`.
+    pub fn open_mode(&mut self, open_mode: OpenMode) -> &mut TunTapBuilder {
+        self.open_mode = open_mode;
+        self
+    }
+
+    /// With `false` the device stays open in programs this process execs,
+    /// for handing it to a helper on purpose. Defaults to `true`.
+    pub fn cloexec(&mut self, cloexec: bool) -> &mut TunTapBuilder {
+        self.cloexec = cloexec;
+        self
+    }
+
+    /// Sets or clears IFF_NOARP, see `TunTap::set_noarp`. Defaults to `true`
+    /// for TUN devices, TAP devices keep ARP unless this is set.
+    pub fn noarp(&mut self, noarp: bool) -> &mut TunTapBuilder {
+        self.noarp = Some(noarp);
+        self
+    }
+
+    /// Brings the device up once everything else is configured. With
+    /// `false` it is left down for the caller to bring up with
+    /// `TunTap::bring_up`. Defaults to `true`.
+    pub fn bring_up(&mut self, bring_up: bool) -> &mut TunTapBuilder {
+        self.bring_up = bring_up;
+        self
+    }
+
+    /// Tolerates bringing the device up, or setting the default IFF_NOARP
+    /// on a TUN device, failing with EPERM. Without CAP_NET_ADMIN a process
+    /// can still attach to a persistent device it owns, but not change its
+    /// flags; `build_with_status` reports whether that happened.
+    pub fn best_effort_up(&mut self, best_effort_up: bool) -> &mut TunTapBuilder {
+        self.best_effort_up = best_effort_up;
+        self
+    }
+
+    /// Creates the device and applies the configuration in order. If any
+    /// step fails, a device this call created is removed again, persistent
+    /// or not, so no half-configured interface is left behind. A device
+    /// that already existed is left in place.
+    pub fn build(&self) -> Result<TunTap, TunTapError> {
+        self.build_with_status().map(|(tuntap, _)| tuntap)
+    }
+
+    /// Like `build`, but also reports the steps `best_effort_up` let fail.
+    pub fn build_with_status(&self) -> Result<(TunTap, BuildStatus), TunTapError> {
+        let mut flags = 0;
+        if !self.packet_info {
+            flags |= IFF_NO_PI;
+        }
+        if self.multi_queue {
+            flags |= IFF_MULTI_QUEUE;
+        }
+        if self.vnet_hdr {
+            flags |= IFF_VNET_HDR;
+        }
+        if self.exclusive {
+            flags |= IFF_TUN_EXCL;
+        }
+
+        // Wildcard and empty names always get a new device
+        let existed = !self.name.is_empty() && !self.name.contains('%') &&
+                      Path::new(SYS_CLASS_NET).join(&self.name).exists();
+        let tuntap = TunTap::open_with_flags(&self.device_path, self.typ, &self.name, flags,
+                                             self.open_mode)?;
+        match self.configure(&tuntap) {
+            Ok(status) => Ok((tuntap, status)),
+            Err(err) => {
+                // Non-persistent devices go away with the last handle
+                if self.persistent && !existed {
+                    let _ = tuntap.set_persistent(false);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn configure(&self, tuntap: &TunTap) -> Result<BuildStatus, TunTapError> {
+        let mut status = BuildStatus::default();
+        if !self.cloexec {
+            tuntap.set_cloexec(false)?;
+        }
+        if self.persistent {
+            tuntap.set_persistent(true)?;
+        }
+        if let Some(mtu) = self.mtu {
+            tuntap.set_mtu(mtu)?;
+        }
+        if let Some(mac) = self.mac {
+            tuntap.set_mac(mac)?;
+        }
+        match (self.noarp, self.typ) {
+            (Some(noarp), _) => tuntap.set_noarp(noarp)?,
+            (None, TunTapType::Tun) => match tuntap.set_noarp(true) {
+                // Only the default, so tolerated like bringing the device up
+                Err(TunTapError::Ioctl { source, .. })
+                    if self.best_effort_up && source.raw_os_error() == Some(EPERM) => {
+                    status.noarp_error = Some(source);
+                }
+                res => res?,
+            },
+            (None, TunTapType::Tap) => {}
+        }
+        if let Some((addr, prefix_len)) = self.ipv4 {
+            tuntap.add_ipv4_addr_with_prefix(addr, prefix_len)?;
+        }
+        if self.bring_up {
+            match tuntap.bring_up() {
+                Err(TunTapError::Ioctl { source, .. })
+                    if self.best_effort_up && source.raw_os_error() == Some(EPERM) => {
+                    status.up_error = Some(source);
+                }
+                res => res?,
+            }
+        }
+        Ok(status)
+    }
+}
+
+/// What `TunTapBuilder::build_with_status` had to skip.
+#[derive(Debug, Default)]
+pub struct BuildStatus {
+    /// The EPERM bringing the device up failed with under `best_effort_up`.
+    /// The device is as up or down as it was before.
+    pub up_error: Option<io::Error>,
+    /// The EPERM setting the default IFF_NOARP on a TUN device failed with
+    /// under `best_effort_up`.
+    pub noarp_error: Option<io::Error>,
+}
+
+impl BuildStatus {
+    /// Whether every step was applied.
+    pub fn is_complete(&self) -> bool {
+        self.up_error.is_none() && self.noarp_error.is_none()
+    }
+}
+
+/// Device settings as plain data, e.g. deserialized from a configuration
+/// file with the `serde` feature. See `TunTap::from_config`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct TunTapConfig {
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub typ: TunTapType,
+    pub mtu: Option<u32>,
+    /// Addresses with their prefix lengths, see `TunTap::set_addresses`.
+    pub addresses: Vec<(IpAddr, u8)>,
+    pub mac: Option<[u8; 6]>,
+}
+
+impl Default for TunTapConfig {
+    fn default() -> TunTapConfig {
+        TunTapConfig {
+            name: String::new(),
+            typ: TunTapType::Tun,
+            mtu: None,
+            addresses: Vec::new(),
+            mac: None,
+        }
+    }
+}
+
+impl TunTapConfig {
+    /// Creates the device, leaving it down until all addresses are added.
+    pub fn build(&self) -> Result<TunTap, TunTapError> {
+        let mut builder = TunTapBuilder::new();
+        builder.name(&self.name).typ(self.typ).bring_up(false);
+        if let Some(mtu) = self.mtu {
+            builder.mtu(mtu);
+        }
+        if let Some(mac) = self.mac {
+            builder.mac(mac);
+        }
+
+        let tuntap = builder.build()?;
+        tuntap.set_addresses(&self.addresses)?;
+        tuntap.bring_up()?;
+        Ok(tuntap)
+    }
+}
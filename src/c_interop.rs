@@ -1,13 +1,23 @@
-use libc::{c_int, c_ulong, c_short, sockaddr_in, in6_addr, sockaddr};
+use libc::{c_int, c_ulong, c_short, sockaddr_in};
+#[cfg(target_os = "linux")]
+use libc::{c_char, in6_addr, sockaddr};
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+use libc::{sockaddr_in6, time_t};
 
 include!(concat!(env!("OUT_DIR"), "/constants.rs"));
 
+// The kernel always copies a whole `struct ifreq` in and out of userspace,
+// so every ifreq-shaped struct carries at least that much trailing space.
+
+#[cfg(target_os = "linux")]
 #[repr(C)]
 pub struct in_ifreq {
     pub ifr_name: [u8; IFNAMSIZ],
     pub ifr_addr: sockaddr_in,
+    pub ifr_pad: [u8; IFREQ_PAD],
 }
 
+#[cfg(target_os = "linux")]
 #[repr(C)]
 pub struct in6_ifreq {
     pub ifr6_addr: in6_addr,
@@ -19,16 +29,112 @@ pub struct in6_ifreq {
 pub struct ioctl_flags_data {
     pub ifr_name: [u8; IFNAMSIZ],
     pub ifr_flags: c_short,
+    pub ifr_pad: [u8; IFREQ_PAD],
 }
 
+#[cfg(target_os = "linux")]
 #[repr(C)]
 pub struct ioctl_ifindex_data {
     pub ifr_name: [u8; IFNAMSIZ],
     pub ifr_ifindex: c_int,
+    pub ifr_pad: [u8; IFREQ_PAD],
 }
 
+#[cfg(target_os = "linux")]
+#[repr(C)]
+pub struct ioctl_newname {
+    pub ifr_name: [u8; IFNAMSIZ],
+    pub ifr_newname: [u8; IFNAMSIZ],
+    pub ifr_pad: [u8; IFREQ_PAD],
+}
+
+#[cfg(target_os = "linux")]
 #[repr(C)]
 pub struct ioctl_mac {
     pub ifr_name: [u8; IFNAMSIZ],
     pub ifr_addr: sockaddr,
+    pub ifr_pad: [u8; IFREQ_PAD],
+}
+
+#[repr(C)]
+pub struct ioctl_mtu_data {
+    pub ifr_name: [u8; IFNAMSIZ],
+    pub ifr_mtu: c_int,
+    pub ifr_pad: [u8; IFREQ_PAD],
+}
+
+#[cfg(target_os = "linux")]
+#[repr(C)]
+pub struct ioctl_qlen_data {
+    pub ifr_name: [u8; IFNAMSIZ],
+    pub ifr_qlen: c_int,
+    pub ifr_pad: [u8; IFREQ_PAD],
+}
+
+// The kernel's struct rtentry for SIOCADDRT on AF_INET sockets
+#[cfg(target_os = "linux")]
+#[repr(C)]
+pub struct rtentry {
+    pub rt_pad1: c_ulong,
+    pub rt_dst: sockaddr_in,
+    pub rt_gateway: sockaddr_in,
+    pub rt_genmask: sockaddr_in,
+    pub rt_flags: u16,
+    pub rt_pad2: c_short,
+    pub rt_pad3: c_ulong,
+    pub rt_pad4: c_ulong,
+    pub rt_metric: c_short,
+    pub rt_dev: *mut c_char,
+    pub rt_mtu: c_ulong,
+    pub rt_window: c_ulong,
+    pub rt_irtt: u16,
+}
+
+// SIOCADDRT on AF_INET6 sockets takes this instead
+#[cfg(target_os = "linux")]
+#[repr(C)]
+pub struct in6_rtmsg {
+    pub rtmsg_dst: in6_addr,
+    pub rtmsg_src: in6_addr,
+    pub rtmsg_gateway: in6_addr,
+    pub rtmsg_type: u32,
+    pub rtmsg_dst_len: u16,
+    pub rtmsg_src_len: u16,
+    pub rtmsg_metric: u32,
+    pub rtmsg_info: c_ulong,
+    pub rtmsg_flags: u32,
+    pub rtmsg_ifindex: c_int,
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+#[repr(C)]
+pub struct in_aliasreq {
+    pub ifra_name: [u8; IFNAMSIZ],
+    pub ifra_addr: sockaddr_in,
+    pub ifra_dstaddr: sockaddr_in,
+    pub ifra_mask: sockaddr_in,
+    #[cfg(target_os = "freebsd")]
+    pub ifra_vhid: c_int,
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+#[repr(C)]
+pub struct in6_addrlifetime {
+    pub ia6t_expire: time_t,
+    pub ia6t_preferred: time_t,
+    pub ia6t_vltime: u32,
+    pub ia6t_pltime: u32,
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+#[repr(C)]
+pub struct in6_aliasreq {
+    pub ifra_name: [u8; IFNAMSIZ],
+    pub ifra_addr: sockaddr_in6,
+    pub ifra_dstaddr: sockaddr_in6,
+    pub ifra_prefixmask: sockaddr_in6,
+    pub ifra_flags: c_int,
+    pub ifra_lifetime: in6_addrlifetime,
+    #[cfg(target_os = "freebsd")]
+    pub ifra_vhid: c_int,
 }
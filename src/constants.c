@@ -1,25 +1,86 @@
 #include <stdio.h>
 #include <sys/ioctl.h>
 #include <net/if.h>
+#include <net/route.h>
 #include <linux/if_tun.h>
+#include <linux/sockios.h>
+#include <linux/virtio_net.h>
 
 #define RUST_CONST(name, type, printf_type) printf("pub const " #name ": " #type " = " printf_type ";\n", name);
 
 int main() {
     RUST_CONST(TUNSETIFF, c_ulong, "%lu")
+    RUST_CONST(TUNGETIFF, c_ulong, "%lu")
+    RUST_CONST(TUNSETPERSIST, c_ulong, "%lu")
+    RUST_CONST(TUNSETOWNER, c_ulong, "%lu")
+    RUST_CONST(TUNSETGROUP, c_ulong, "%lu")
+    RUST_CONST(TUNSETQUEUE, c_ulong, "%lu")
+    RUST_CONST(TUNSETOFFLOAD, c_ulong, "%lu")
+    RUST_CONST(TUNGETVNETHDRSZ, c_ulong, "%lu")
+    RUST_CONST(TUNSETVNETHDRSZ, c_ulong, "%lu")
+    RUST_CONST(TUNGETSNDBUF, c_ulong, "%lu")
+    RUST_CONST(TUNSETSNDBUF, c_ulong, "%lu")
+    RUST_CONST(TUNATTACHFILTER, c_ulong, "%lu")
+    RUST_CONST(TUNDETACHFILTER, c_ulong, "%lu")
+    RUST_CONST(SIOCGIFADDR, c_ulong, "%d")
     RUST_CONST(SIOCSIFADDR, c_ulong, "%d")
+    RUST_CONST(SIOCDIFADDR, c_ulong, "%d")
+    RUST_CONST(SIOCGIFNETMASK, c_ulong, "%d")
+    RUST_CONST(SIOCSIFNETMASK, c_ulong, "%d")
+    RUST_CONST(SIOCSIFDSTADDR, c_ulong, "%d")
+    RUST_CONST(SIOCGIFBRDADDR, c_ulong, "%d")
+    RUST_CONST(SIOCSIFBRDADDR, c_ulong, "%d")
+    RUST_CONST(SIOCADDRT, c_ulong, "%d")
+    RUST_CONST(SIOCDELRT, c_ulong, "%d")
     RUST_CONST(SIOCGIFINDEX, c_ulong, "%d")
+    RUST_CONST(SIOCSIFNAME, c_ulong, "%d")
     RUST_CONST(SIOCGIFFLAGS, c_ulong, "%d")
     RUST_CONST(SIOCSIFFLAGS, c_ulong, "%d")
+    RUST_CONST(SIOCGIFHWADDR, c_ulong, "%d")
     RUST_CONST(SIOCSIFHWADDR, c_ulong, "%d")
+    RUST_CONST(SIOCGIFMTU, c_ulong, "%d")
+    RUST_CONST(SIOCSIFMTU, c_ulong, "%d")
+    RUST_CONST(SIOCGIFTXQLEN, c_ulong, "%d")
+    RUST_CONST(SIOCSIFTXQLEN, c_ulong, "%d")
+    RUST_CONST(SIOCBRADDIF, c_ulong, "%d")
+    RUST_CONST(SIOCBRDELIF, c_ulong, "%d")
 
     RUST_CONST(IFF_TUN, c_short, "%d")
     RUST_CONST(IFF_TAP, c_short, "%d")
     RUST_CONST(IFF_NO_PI, c_short, "%d")
+    RUST_CONST(IFF_MULTI_QUEUE, c_short, "%d")
+    RUST_CONST(IFF_VNET_HDR, c_short, "%d")
+    RUST_CONST(IFF_ATTACH_QUEUE, c_short, "%d")
+    RUST_CONST(IFF_DETACH_QUEUE, c_short, "%d")
+    // 0x8000, which only fits a c_short as a negative value
+    printf("pub const IFF_TUN_EXCL: c_short = %d;\n", (short)IFF_TUN_EXCL);
     RUST_CONST(IFF_UP, c_short, "%d")
+    RUST_CONST(IFF_BROADCAST, c_short, "%d")
+    RUST_CONST(IFF_POINTOPOINT, c_short, "%d")
     RUST_CONST(IFF_RUNNING, c_short, "%d")
+    RUST_CONST(IFF_NOARP, c_short, "%d")
+    RUST_CONST(IFF_PROMISC, c_short, "%d")
+    RUST_CONST(IFF_MULTICAST, c_short, "%d")
+
+    RUST_CONST(RTF_UP, u16, "%d")
+    RUST_CONST(RTF_GATEWAY, u16, "%d")
+    RUST_CONST(RTF_HOST, u16, "%d")
+
+    RUST_CONST(TUN_F_CSUM, u32, "%d")
+    RUST_CONST(TUN_F_TSO4, u32, "%d")
+    RUST_CONST(TUN_F_TSO6, u32, "%d")
+    RUST_CONST(TUN_F_UFO, u32, "%d")
+
+    RUST_CONST(VIRTIO_NET_HDR_F_NEEDS_CSUM, u8, "%d")
+    RUST_CONST(VIRTIO_NET_HDR_F_DATA_VALID, u8, "%d")
+    RUST_CONST(VIRTIO_NET_HDR_GSO_NONE, u8, "%d")
+    RUST_CONST(VIRTIO_NET_HDR_GSO_TCPV4, u8, "%d")
+    RUST_CONST(VIRTIO_NET_HDR_GSO_UDP, u8, "%d")
+    RUST_CONST(VIRTIO_NET_HDR_GSO_TCPV6, u8, "%d")
+    RUST_CONST(VIRTIO_NET_HDR_GSO_ECN, u8, "%d")
 
     RUST_CONST(IFNAMSIZ, usize, "%d")
+    printf("pub const IFREQ_PAD: usize = %zu;\n", sizeof(struct ifreq) - IFNAMSIZ);
 
     return 0;
 }
@@ -0,0 +1,32 @@
+#include <stdio.h>
+#include <sys/ioctl.h>
+#include <sys/socket.h>
+#include <sys/sockio.h>
+#include <net/if.h>
+#include <netinet/in.h>
+#include <netinet6/in6_var.h>
+
+#define RUST_CONST(name, type, printf_type) printf("pub const " #name ": " #type " = " printf_type ";\n", name);
+
+int main() {
+    RUST_CONST(SIOCAIFADDR, c_ulong, "%lu")
+    RUST_CONST(SIOCAIFADDR_IN6, c_ulong, "%lu")
+    RUST_CONST(SIOCGIFFLAGS, c_ulong, "%lu")
+    RUST_CONST(SIOCSIFFLAGS, c_ulong, "%lu")
+    RUST_CONST(SIOCGIFMTU, c_ulong, "%lu")
+    RUST_CONST(SIOCSIFMTU, c_ulong, "%lu")
+
+    RUST_CONST(IFF_UP, c_short, "%d")
+    RUST_CONST(IFF_BROADCAST, c_short, "%d")
+    RUST_CONST(IFF_POINTOPOINT, c_short, "%d")
+    RUST_CONST(IFF_RUNNING, c_short, "%d")
+    RUST_CONST(IFF_NOARP, c_short, "%d")
+    RUST_CONST(IFF_PROMISC, c_short, "%d")
+    // 0x8000 on macOS, which only fits a c_short as a negative value
+    printf("pub const IFF_MULTICAST: c_short = %d;\n", (short)IFF_MULTICAST);
+
+    RUST_CONST(IFNAMSIZ, usize, "%d")
+    printf("pub const IFREQ_PAD: usize = %zu;\n", sizeof(struct ifreq) - IFNAMSIZ);
+
+    return 0;
+}
@@ -0,0 +1,100 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum TunTapError {
+    /// The interface name doesn't fit into `IFNAMSIZ`.
+    NameTooLong { max: usize },
+    /// The interface name contains characters the kernel won't accept.
+    InvalidName,
+    /// A network prefix length is out of range for its address family.
+    InvalidPrefixLength { len: u8, max: u8 },
+    /// The operation only makes sense on a TAP device.
+    RequiresTap,
+    /// Opening the tun device node failed.
+    DeviceOpen(io::Error),
+    /// The tun device node doesn't exist (`ENOENT`) or has no driver behind
+    /// it (`ENODEV`), usually because the tun module isn't loaded. See
+    /// `TunTap::ensure_device_node`.
+    DeviceNodeMissing { path: PathBuf, source: io::Error },
+    /// An ioctl failed; `call` is the name of the request, e.g. `"TUNSETIFF"`.
+    Ioctl { call: &'static str, source: io::Error },
+    /// A packet didn't fit into the read buffer; `len` bytes of it were read.
+    FrameTruncated { len: usize },
+    /// Any other I/O error.
+    Io(io::Error),
+}
+
+impl TunTapError {
+    pub(crate) fn ioctl(call: &'static str) -> TunTapError {
+        TunTapError::Ioctl {
+            call,
+            source: io::Error::last_os_error(),
+        }
+    }
+}
+
+impl fmt::Display for TunTapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TunTapError::NameTooLong { max } => {
+                write!(f, "Interface name too long, max length is {}", max)
+            }
+            TunTapError::InvalidName => write!(f, "Invalid interface name"),
+            TunTapError::InvalidPrefixLength { len, max } => {
+                write!(f, "Invalid prefix length /{}, max is /{}", len, max)
+            }
+            TunTapError::RequiresTap => write!(f, "Operation requires a TAP device"),
+            TunTapError::DeviceOpen(ref err) => write!(f, "Couldn't open tun device: {}", err),
+            TunTapError::DeviceNodeMissing { ref path, ref source } => {
+                write!(f, "Couldn't open {}: {}; load the tun module with `modprobe tun`, \
+                           or create the node with `mknod {} c 10 200`",
+                       path.display(), source, path.display())
+            }
+            TunTapError::Ioctl { call, ref source } => write!(f, "{} failed: {}", call, source),
+            TunTapError::FrameTruncated { len } => {
+                write!(f, "Packet truncated to the {} byte buffer", len)
+            }
+            TunTapError::Io(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for TunTapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            TunTapError::DeviceOpen(ref err) => Some(err),
+            TunTapError::DeviceNodeMissing { ref source, .. } => Some(source),
+            TunTapError::Ioctl { ref source, .. } => Some(source),
+            TunTapError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TunTapError {
+    fn from(err: io::Error) -> TunTapError {
+        TunTapError::Io(err)
+    }
+}
+
+impl From<TunTapError> for io::Error {
+    fn from(err: TunTapError) -> io::Error {
+        match err {
+            TunTapError::DeviceOpen(err) |
+            TunTapError::Ioctl { source: err, .. } |
+            TunTapError::Io(err) => err,
+            // Kept whole for the advice in the message
+            err @ TunTapError::DeviceNodeMissing { .. } => {
+                io::Error::new(io::ErrorKind::NotFound, err)
+            }
+            // Kept whole so callers can downcast to get at the length
+            err @ TunTapError::FrameTruncated { .. } => {
+                io::Error::new(io::ErrorKind::InvalidData, err)
+            }
+            err => io::Error::new(io::ErrorKind::InvalidInput, err.to_string()),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+/// An Ethernet frame as read from or written to a TAP device, borrowing its
+/// payload. See `TunTap::read_frame` and `TunTap::write_frame`.
+///
+/// VLAN tags aren't parsed: a tagged frame has `ethertype` 0x8100 and the
+/// tag at the front of `payload`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct EthernetFrame<'a> {
+    pub dst: [u8; 6],
+    pub src: [u8; 6],
+    pub ethertype: u16,
+    pub payload: &'a [u8],
+}
+
+impl<'a> EthernetFrame<'a> {
+    pub const HEADER_SIZE: usize = 14;
+
+    /// Splits `frame` into its header fields and payload.
+    pub fn parse(frame: &'a [u8]) -> Option<EthernetFrame<'a>> {
+        if frame.len() < EthernetFrame::HEADER_SIZE {
+            return None;
+        }
+        let mut dst = [0u8; 6];
+        let mut src = [0u8; 6];
+        dst.copy_from_slice(&frame[0..6]);
+        src.copy_from_slice(&frame[6..12]);
+        Some(EthernetFrame {
+            dst,
+            src,
+            ethertype: u16::from_be_bytes([frame[12], frame[13]]),
+            payload: &frame[EthernetFrame::HEADER_SIZE..],
+        })
+    }
+
+    pub fn header_bytes(&self) -> [u8; EthernetFrame::HEADER_SIZE] {
+        let mut bytes = [0u8; EthernetFrame::HEADER_SIZE];
+        bytes[0..6].copy_from_slice(&self.dst);
+        bytes[6..12].copy_from_slice(&self.src);
+        bytes[12..14].copy_from_slice(&self.ethertype.to_be_bytes());
+        bytes
+    }
+}
@@ -0,0 +1,11 @@
+//! Interface flags as used by `TunTap::get_flags` and `TunTap::set_flags`.
+
+use c_interop;
+
+pub const IFF_UP: i32 = c_interop::IFF_UP as u16 as i32;
+pub const IFF_BROADCAST: i32 = c_interop::IFF_BROADCAST as u16 as i32;
+pub const IFF_POINTOPOINT: i32 = c_interop::IFF_POINTOPOINT as u16 as i32;
+pub const IFF_RUNNING: i32 = c_interop::IFF_RUNNING as u16 as i32;
+pub const IFF_NOARP: i32 = c_interop::IFF_NOARP as u16 as i32;
+pub const IFF_PROMISC: i32 = c_interop::IFF_PROMISC as u16 as i32;
+pub const IFF_MULTICAST: i32 = c_interop::IFF_MULTICAST as u16 as i32;
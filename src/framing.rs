@@ -0,0 +1,40 @@
+use std::io;
+
+/// Length-prefixed framing for carrying packets over a stream such as TCP.
+///
+/// Each frame is a 2-byte big-endian length followed by the packet. The
+/// codec does no I/O itself: `encode` appends to an outgoing buffer and
+/// `decode` takes complete frames off the front of an incoming one.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct FrameCodec;
+
+impl FrameCodec {
+    pub const MAX_PACKET_SIZE: usize = u16::MAX as usize;
+
+    /// Appends `packet` to `out` as one frame. Fails for packets larger
+    /// than `MAX_PACKET_SIZE`.
+    pub fn encode(packet: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        if packet.len() > FrameCodec::MAX_PACKET_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Packet too large for a 2-byte length prefix"));
+        }
+        out.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+        out.extend_from_slice(packet);
+        Ok(())
+    }
+
+    /// Removes the first complete frame from `buf` and returns its packet,
+    /// or returns `None` and leaves `buf` alone if more data is needed.
+    pub fn decode(buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+        if buf.len() < 2 {
+            return None;
+        }
+        let len = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+        if buf.len() < 2 + len {
+            return None;
+        }
+        let packet = buf[2..2 + len].to_vec();
+        buf.drain(..2 + len);
+        Some(packet)
+    }
+}
@@ -0,0 +1,28 @@
+/// The IP version of a raw packet, see `detect_ip_version`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum IpVersion {
+    V4,
+    V6,
+}
+
+impl IpVersion {
+    /// The EtherType for packets of this version, as in the `proto` of a
+    /// packet information header.
+    pub fn ethertype(self) -> u16 {
+        match self {
+            IpVersion::V4 => 0x0800,
+            IpVersion::V6 => 0x86dd,
+        }
+    }
+}
+
+/// Tells IPv4 from IPv6 packets by the version in the first nibble, as
+/// TUN devices without packet info give no other indication. Returns
+/// `None` for empty packets and any other version.
+pub fn detect_ip_version(packet: &[u8]) -> Option<IpVersion> {
+    match packet.first().map(|b| b >> 4) {
+        Some(4) => Some(IpVersion::V4),
+        Some(6) => Some(IpVersion::V6),
+        _ => None,
+    }
+}
@@ -1,7 +1,79 @@
 extern crate libc;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "mio")]
+extern crate mio;
+#[cfg(feature = "async-std")]
+extern crate async_io;
+#[cfg(feature = "async-std")]
+extern crate futures_io;
+#[cfg(feature = "serde")]
+extern crate serde;
 
-pub use tuntap::TunTap;
+#[cfg(target_os = "linux")]
+pub use batch::PacketBatch;
+#[cfg(target_os = "linux")]
+pub use buffered::BufferedTunTap;
+#[cfg(target_os = "linux")]
+pub use builder::{BuildStatus, TunTapBuilder, TunTapConfig};
+pub use error::TunTapError;
+pub use framing::FrameCodec;
+pub use ip::{detect_ip_version, IpVersion};
+#[cfg(target_os = "linux")]
+pub use ethernet::EthernetFrame;
+#[cfg(target_os = "linux")]
+pub use filter::SockFilter;
+#[cfg(target_os = "linux")]
+pub use offload::{OffloadFlags, VnetHdr};
+#[cfg(target_os = "linux")]
+pub use split::{TunReader, TunTapShared, TunWriter};
+#[cfg(target_os = "linux")]
+pub use stats::{Duplex, InterfaceStats, LinkInfo};
+#[cfg(target_os = "linux")]
+pub use tuntap::{list_devices, OpenMode, PacketInfo, QueueInfo};
+pub use tuntap::{validate_name, TunTap, TunTapType, MAX_NAME_LEN};
 pub use tuntap::TunTapType::{Tun, Tap};
+#[cfg(all(feature = "tokio", target_os = "linux"))]
+pub use tokio_support::AsyncTunTap;
+#[cfg(all(feature = "async-std", target_os = "linux"))]
+pub use async_std_support::AsyncStdTunTap;
 
+pub mod flags;
+#[cfg(target_os = "linux")]
+pub mod raw;
+
+#[cfg(target_os = "linux")]
+mod batch;
+#[cfg(target_os = "linux")]
+mod buffered;
+#[cfg(target_os = "linux")]
+mod builder;
+mod error;
+#[cfg(target_os = "linux")]
+mod ethernet;
+#[cfg(target_os = "linux")]
+mod filter;
+mod framing;
+mod ip;
+#[cfg(target_os = "linux")]
+mod offload;
+#[cfg(target_os = "linux")]
+mod split;
+#[cfg(target_os = "linux")]
+mod stats;
+#[cfg(target_os = "linux")]
+mod tuntap;
+#[cfg(target_os = "macos")]
+#[path = "macos.rs"]
+mod tuntap;
+#[cfg(target_os = "freebsd")]
+#[path = "freebsd.rs"]
 mod tuntap;
 mod c_interop;
+mod socket;
+#[cfg(all(feature = "tokio", target_os = "linux"))]
+mod tokio_support;
+#[cfg(all(feature = "async-std", target_os = "linux"))]
+mod async_std_support;
+#[cfg(feature = "mio")]
+mod mio_support;
@@ -0,0 +1,505 @@
+use std::ffi::CStr;
+use std::fmt;
+use std::fs::File;
+use std::io::{IoSlice, IoSliceMut, Read, Write};
+use std::io;
+use std::mem;
+use std::ptr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::unix::prelude::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, RawFd};
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use libc::{c_int, c_char, c_short, c_void, AF_INET, AF_INET6, AF_SYSTEM, AF_SYS_CONTROL,
+           PF_SYSTEM, SOCK_DGRAM, SYSPROTO_CONTROL, UTUN_OPT_IFNAME, CTLIOCGINFO,
+           socket, connect, getsockopt, ioctl, close, fcntl, F_GETFL, F_SETFL, O_NONBLOCK,
+           ctl_info, sockaddr_ctl, sockaddr, sockaddr_in, sockaddr_in6, sa_family_t,
+           socklen_t, in_addr, in6_addr};
+use c_interop::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use error::TunTapError;
+use socket::{self, SocketGuard};
+
+const UTUN_CONTROL_NAME: &[u8] = b"com.apple.net.utun_control";
+
+// utun prepends the address family of each packet as a 4-byte header
+const AF_HEADER_SIZE: usize = 4;
+
+// No expiry for addresses added with SIOCAIFADDR_IN6
+const ND6_INFINITE_LIFETIME: u32 = 0xffffffff;
+
+/// The longest interface name the kernel accepts, in bytes.
+pub const MAX_NAME_LEN: usize = IFNAMSIZ - 1;
+
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum TunTapType {
+    Tun,
+    Tap,
+}
+
+/// A macOS utun device.
+///
+/// Only TUN devices are supported. Reads and writes carry bare IP packets,
+/// the utun address family header is added and stripped internally.
+pub struct TunTap {
+    pub file: File,
+    if_name: [u8; IFNAMSIZ],
+    mtu: AtomicUsize,
+    // Control sockets for the SIOC* ioctls
+    sock: SocketGuard,
+    sock6: AtomicI32,
+}
+
+// The MTU is the last value read or set, Debug doesn't query the kernel
+impl fmt::Debug for TunTap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TunTap")
+            .field("name", &self.get_name())
+            .field("type", &self.get_type())
+            .field("fd", &self.file.as_raw_fd())
+            .field("mtu", &self.mtu.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+impl fmt::Display for TunTap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.get_name())
+    }
+}
+
+impl Drop for TunTap {
+    fn drop(&mut self) {
+        let sock6 = self.sock6.load(Ordering::Relaxed);
+        if sock6 >= 0 {
+            unsafe { close(sock6) };
+        }
+    }
+}
+
+impl AsRawFd for TunTap {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for TunTap {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+// Each call reads or writes exactly one packet, like the inherent methods
+impl Read for TunTap {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        TunTap::read(self, buf)
+    }
+}
+
+impl Write for TunTap {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        TunTap::write(self, buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        TunTap::flush(self)
+    }
+}
+
+impl TunTap {
+    /// Creates a utun device and brings it up.
+    ///
+    /// `name` must be `utunN` to request a specific unit, or empty to let
+    /// the kernel pick the next free one.
+    pub fn new(typ: TunTapType, name: &str) -> Result<TunTap, TunTapError> {
+        if typ == TunTapType::Tap {
+            return Err(io::Error::new(io::ErrorKind::Unsupported,
+                                      "TAP devices aren't available on macOS").into());
+        }
+
+        // Unit 0 means "any", utunN is unit N + 1
+        let unit = match utun_unit(name)? {
+            Some(n) => n + 1,
+            None => 0,
+        };
+
+        let fd = unsafe { socket(PF_SYSTEM, SOCK_DGRAM, SYSPROTO_CONTROL) };
+        if fd < 0 {
+            return Err(TunTapError::DeviceOpen(io::Error::last_os_error()));
+        }
+        let file = unsafe { File::from_raw_fd(fd) };
+        socket::set_cloexec(fd, true)?;
+
+        let mut info: ctl_info = unsafe { mem::zeroed() };
+        for (i, b) in UTUN_CONTROL_NAME.iter().enumerate() {
+            info.ctl_name[i] = *b as c_char;
+        }
+        let res = unsafe { ioctl(fd, CTLIOCGINFO, &mut info) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("CTLIOCGINFO"));
+        }
+
+        let addr = sockaddr_ctl {
+            sc_len: mem::size_of::<sockaddr_ctl>() as u8,
+            sc_family: AF_SYSTEM as u8,
+            ss_sysaddr: AF_SYS_CONTROL as u16,
+            sc_id: info.ctl_id,
+            sc_unit: unit,
+            sc_reserved: [0; 5],
+        };
+        let res = unsafe {
+            connect(fd, &addr as *const sockaddr_ctl as *const sockaddr,
+                    mem::size_of::<sockaddr_ctl>() as socklen_t)
+        };
+        if res < 0 {
+            return Err(TunTapError::DeviceOpen(io::Error::last_os_error()));
+        }
+
+        let mut if_name = [0u8; IFNAMSIZ];
+        let mut len = IFNAMSIZ as socklen_t;
+        let res = unsafe {
+            getsockopt(fd, SYSPROTO_CONTROL, UTUN_OPT_IFNAME,
+                       if_name.as_mut_ptr() as *mut c_void, &mut len)
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let tuntap = TunTap {
+            file,
+            if_name,
+            mtu: AtomicUsize::new(0),
+            sock: SocketGuard::new(AF_INET)?,
+            sock6: AtomicI32::new(-1),
+        };
+        tuntap.get_mtu()?;
+        tuntap.bring_up()?;
+        Ok(tuntap)
+    }
+
+    pub fn get_type(&self) -> TunTapType {
+        TunTapType::Tun
+    }
+
+    /// Returns the interface name as assigned by the kernel.
+    pub fn get_name(&self) -> String {
+        let name = CStr::from_bytes_until_nul(&self.if_name)
+            .expect("Device name should be null-terminated");
+        name.to_string_lossy().into_owned()
+    }
+
+    fn inet6_socket(&self) -> Result<c_int, TunTapError> {
+        let sock = self.sock6.load(Ordering::Acquire);
+        if sock >= 0 {
+            return Ok(sock);
+        }
+
+        let sock = SocketGuard::new(AF_INET6)?;
+        match self.sock6.compare_exchange(-1, sock.0, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => Ok(sock.into_raw()),
+            // Another thread got there first, the guard closes ours
+            Err(other) => Ok(other),
+        }
+    }
+
+    pub fn get_flags(&self) -> Result<i32, TunTapError> {
+        let mut req = ioctl_flags_data {
+            ifr_name: self.if_name,
+            ifr_flags: 0,
+            ifr_pad: [0; IFREQ_PAD],
+        };
+
+        let res = unsafe { ioctl(self.sock.0, SIOCGIFFLAGS, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("SIOCGIFFLAGS"));
+        }
+        Ok(req.ifr_flags as u16 as i32)
+    }
+
+    pub fn set_flags(&self, flags: i32) -> Result<(), TunTapError> {
+        let mut req = ioctl_flags_data {
+            ifr_name: self.if_name,
+            ifr_flags: flags as c_short,
+            ifr_pad: [0; IFREQ_PAD],
+        };
+
+        let res = unsafe { ioctl(self.sock.0, SIOCSIFFLAGS, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("SIOCSIFFLAGS"));
+        }
+        Ok(())
+    }
+
+    pub fn bring_up(&self) -> Result<(), TunTapError> {
+        let flags = self.get_flags()?;
+        let up = (IFF_UP | IFF_RUNNING) as i32;
+        if flags & up == up {
+            // Already up
+            return Ok(());
+        }
+        self.set_flags(flags | up)
+    }
+
+    pub fn bring_down(&self) -> Result<(), TunTapError> {
+        let flags = self.get_flags()?;
+        self.set_flags(flags & !((IFF_UP | IFF_RUNNING) as i32))
+    }
+
+    /// Returns the underlying file without closing it, e.g. to hand the
+    /// device over to other code.
+    pub fn into_inner(self) -> File {
+        let this = mem::ManuallyDrop::new(self);
+        let sock6 = this.sock6.load(Ordering::Relaxed);
+        if sock6 >= 0 {
+            unsafe { close(sock6) };
+        }
+        // `this` is never used again, so each field is moved out once
+        unsafe {
+            drop(ptr::read(&this.sock));
+            ptr::read(&this.file)
+        }
+    }
+
+    /// Closes the device, reporting the errors dropping it would ignore.
+    pub fn close(self) -> io::Result<()> {
+        let fd = self.into_inner().into_raw_fd();
+        if unsafe { close(fd) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Whether the interface is administratively up.
+    pub fn is_up(&self) -> Result<bool, TunTapError> {
+        Ok(self.get_flags()? & IFF_UP as i32 != 0)
+    }
+
+    /// Whether the interface is operational, i.e. up and with a carrier.
+    pub fn is_running(&self) -> Result<bool, TunTapError> {
+        Ok(self.get_flags()? & IFF_RUNNING as i32 != 0)
+    }
+
+    /// Adds an IPv4 address with a /32 prefix. utun is a point-to-point
+    /// interface, so the address doubles as the destination address.
+    pub fn add_ipv4_addr(&self, addr: Ipv4Addr) -> Result<(), TunTapError> {
+        self.add_ipv4_addr_with_prefix(addr, 32)
+    }
+
+    pub fn add_ipv4_addr_with_prefix(&self, addr: Ipv4Addr, prefix_len: u8)
+                                     -> Result<(), TunTapError> {
+        if prefix_len > 32 {
+            return Err(TunTapError::InvalidPrefixLength { len: prefix_len, max: 32 });
+        }
+        let mask = match prefix_len {
+            0 => 0,
+            len => !0u32 << (32 - len),
+        };
+
+        let mut req = in_aliasreq {
+            ifra_name: self.if_name,
+            ifra_addr: ipv4_sockaddr(addr),
+            ifra_dstaddr: ipv4_sockaddr(addr),
+            ifra_mask: ipv4_sockaddr(Ipv4Addr::from(mask)),
+        };
+        let res = unsafe { ioctl(self.sock.0, SIOCAIFADDR, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("SIOCAIFADDR"));
+        }
+        Ok(())
+    }
+
+    /// Adds an IPv6 address with a /64 prefix.
+    pub fn add_ipv6_addr(&self, addr: Ipv6Addr) -> Result<(), TunTapError> {
+        self.add_ipv6_addr_with_prefix(addr, 64)
+    }
+
+    pub fn add_ipv6_addr_with_prefix(&self, addr: Ipv6Addr, prefix_len: u8)
+                                     -> Result<(), TunTapError> {
+        if prefix_len > 128 {
+            return Err(TunTapError::InvalidPrefixLength { len: prefix_len, max: 128 });
+        }
+        let mask = match prefix_len {
+            0 => 0,
+            len => !0u128 << (128 - len),
+        };
+
+        let mut req = in6_aliasreq {
+            ifra_name: self.if_name,
+            ifra_addr: ipv6_sockaddr(addr),
+            ifra_dstaddr: unsafe { mem::zeroed() },
+            ifra_prefixmask: ipv6_sockaddr(Ipv6Addr::from(mask)),
+            ifra_flags: 0,
+            ifra_lifetime: in6_addrlifetime {
+                ia6t_expire: 0,
+                ia6t_preferred: 0,
+                ia6t_vltime: ND6_INFINITE_LIFETIME,
+                ia6t_pltime: ND6_INFINITE_LIFETIME,
+            },
+        };
+        let res = unsafe { ioctl(self.inet6_socket()?, SIOCAIFADDR_IN6, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("SIOCAIFADDR_IN6"));
+        }
+        Ok(())
+    }
+
+    pub fn add_address(&self, addr: IpAddr) -> Result<(), TunTapError> {
+        match addr {
+            IpAddr::V4(value) => self.add_ipv4_addr(value),
+            IpAddr::V6(value) => self.add_ipv6_addr(value),
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), TunTapError> {
+        let fd = self.file.as_raw_fd();
+        let flags = unsafe { fcntl(fd, F_GETFL) };
+        if flags < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let flags = if nonblocking {
+            flags | O_NONBLOCK
+        } else {
+            flags & !O_NONBLOCK
+        };
+        let res = unsafe { fcntl(fd, F_SETFL, flags) };
+        if res < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    /// Controls whether the device is closed when the process execs. It is
+    /// by default, so children don't inherit the device by accident.
+    pub fn set_cloexec(&self, cloexec: bool) -> Result<(), TunTapError> {
+        Ok(socket::set_cloexec(self.file.as_raw_fd(), cloexec)?)
+    }
+
+    pub fn set_mtu(&self, mtu: u32) -> Result<(), TunTapError> {
+        let mut req = ioctl_mtu_data {
+            ifr_name: self.if_name,
+            ifr_mtu: mtu as c_int,
+            ifr_pad: [0; IFREQ_PAD],
+        };
+        let res = unsafe { ioctl(self.sock.0, SIOCSIFMTU, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("SIOCSIFMTU"));
+        }
+        self.mtu.store(mtu as usize, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn get_mtu(&self) -> Result<u32, TunTapError> {
+        let mut req = ioctl_mtu_data {
+            ifr_name: self.if_name,
+            ifr_mtu: 0,
+            ifr_pad: [0; IFREQ_PAD],
+        };
+        let res = unsafe { ioctl(self.sock.0, SIOCGIFMTU, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("SIOCGIFMTU"));
+        }
+        self.mtu.store(req.ifr_mtu as usize, Ordering::Relaxed);
+        Ok(req.ifr_mtu as u32)
+    }
+
+    /// Reads a single IP packet into `buffer`.
+    ///
+    /// A packet that doesn't fit is truncated by the kernel and the rest of
+    /// it is lost. Reads interrupted by a signal are retried.
+    pub fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut header = [0u8; AF_HEADER_SIZE];
+        let len = retry_eintr(|| {
+            self.file.read_vectored(&mut [IoSliceMut::new(&mut header), IoSliceMut::new(buffer)])
+        })?;
+        Ok(len.saturating_sub(AF_HEADER_SIZE))
+    }
+
+    /// Reads a single packet into a newly allocated, MTU-sized buffer.
+    pub fn read_packet(&mut self) -> io::Result<Vec<u8>> {
+        let mut buffer = vec![0u8; self.mtu.load(Ordering::Relaxed)];
+        let len = self.read(&mut buffer)?;
+        buffer.truncate(len);
+        Ok(buffer)
+    }
+
+    /// Every write goes to the kernel as one packet straight away, nothing is
+    /// buffered in between, so this only exists for callers generic over
+    /// `Write` and never has anything to push.
+    pub fn flush(&mut self) -> io::Result<()> {
+        (&self.file).flush()
+    }
+
+    /// Writes a single IP packet. The address family is taken from the IP
+    /// version in the first byte. Writes interrupted by a signal are retried.
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        let family = match data.first().map(|b| b >> 4) {
+            Some(4) => AF_INET,
+            Some(6) => AF_INET6,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           "Not an IPv4 or IPv6 packet")),
+        };
+
+        let header = (family as u32).to_be_bytes();
+        let len = retry_eintr(|| {
+            self.file.write_vectored(&[IoSlice::new(&header), IoSlice::new(data)])
+        })?;
+        if len != header.len() + data.len() {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "Short write"));
+        }
+        Ok(())
+    }
+}
+
+/// Checks that `name` would be accepted by `TunTap::new` without creating
+/// anything: either `utunN` or empty to let the kernel pick the unit.
+pub fn validate_name(name: &str) -> Result<(), TunTapError> {
+    utun_unit(name).map(|_| ())
+}
+
+// Runs `op` again for as long as a signal interrupts it, so packet reads
+// and writes never surface `Interrupted`
+fn retry_eintr<T, F: FnMut() -> io::Result<T>>(mut op: F) -> io::Result<T> {
+    loop {
+        match op() {
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            res => return res,
+        }
+    }
+}
+
+fn utun_unit(name: &str) -> Result<Option<u32>, TunTapError> {
+    if name.is_empty() {
+        return Ok(None);
+    }
+    if name.len() > MAX_NAME_LEN {
+        return Err(TunTapError::NameTooLong { max: MAX_NAME_LEN });
+    }
+    match name.strip_prefix("utun").map(str::parse::<u32>) {
+        // Unit numbers past this overflow once offset by one
+        Some(Ok(n)) if n < u32::MAX => Ok(Some(n)),
+        _ => Err(TunTapError::InvalidName),
+    }
+}
+
+fn ipv4_sockaddr(addr: Ipv4Addr) -> sockaddr_in {
+    sockaddr_in {
+        sin_len: mem::size_of::<sockaddr_in>() as u8,
+        sin_family: AF_INET as sa_family_t,
+        sin_port: 0,
+        sin_addr: in_addr {
+            s_addr: u32::from(addr).to_be(),
+        },
+        sin_zero: [0; 8],
+    }
+}
+
+fn ipv6_sockaddr(addr: Ipv6Addr) -> sockaddr_in6 {
+    let mut sock_addr: sockaddr_in6 = unsafe { mem::zeroed() };
+    sock_addr.sin6_len = mem::size_of::<sockaddr_in6>() as u8;
+    sock_addr.sin6_family = AF_INET6 as sa_family_t;
+    sock_addr.sin6_addr = in6_addr { s6_addr: addr.octets() };
+    sock_addr
+}
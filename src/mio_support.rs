@@ -0,0 +1,24 @@
+use std::io;
+use std::os::unix::io::AsRawFd;
+use mio::{Interest, Registry, Token};
+use mio::event::Source;
+use mio::unix::SourceFd;
+use tuntap::TunTap;
+
+// Registering switches the device into non-blocking mode, as mio requires.
+impl Source for TunTap {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest)
+                -> io::Result<()> {
+        self.set_nonblocking(true)?;
+        SourceFd(&self.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest)
+                  -> io::Result<()> {
+        SourceFd(&self.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.as_raw_fd()).deregister(registry)
+    }
+}
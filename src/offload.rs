@@ -0,0 +1,104 @@
+use std::ops::{BitOr, BitOrAssign};
+use c_interop::*;
+
+/// Offload features for `TunTap::set_offload`, combined with `|`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub struct OffloadFlags(u32);
+
+impl OffloadFlags {
+    /// Userspace handles packets with partial checksums.
+    pub const CSUM: OffloadFlags = OffloadFlags(TUN_F_CSUM);
+    /// Userspace handles TCP segmentation offload over IPv4.
+    pub const TSO4: OffloadFlags = OffloadFlags(TUN_F_TSO4);
+    /// Userspace handles TCP segmentation offload over IPv6.
+    pub const TSO6: OffloadFlags = OffloadFlags(TUN_F_TSO6);
+    /// Userspace handles UDP fragmentation offload.
+    pub const UFO: OffloadFlags = OffloadFlags(TUN_F_UFO);
+
+    pub fn empty() -> OffloadFlags {
+        OffloadFlags(0)
+    }
+
+    pub fn from_bits(bits: u32) -> OffloadFlags {
+        OffloadFlags(bits)
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub fn contains(&self, other: OffloadFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for OffloadFlags {
+    type Output = OffloadFlags;
+
+    fn bitor(self, other: OffloadFlags) -> OffloadFlags {
+        OffloadFlags(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for OffloadFlags {
+    fn bitor_assign(&mut self, other: OffloadFlags) {
+        self.0 |= other.0;
+    }
+}
+
+/// The `struct virtio_net_hdr` in front of every packet on a device
+/// created with a virtio-net header (see `TunTapBuilder::vnet_hdr`).
+///
+/// Fields are in native byte order, which is what the kernel uses unless
+/// told otherwise with TUNSETVNETLE/TUNSETVNETBE.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub struct VnetHdr {
+    pub flags: u8,
+    pub gso_type: u8,
+    pub hdr_len: u16,
+    pub gso_size: u16,
+    pub csum_start: u16,
+    pub csum_offset: u16,
+}
+
+impl VnetHdr {
+    /// Size of the basic header. The mergeable-buffers variant adds a
+    /// 2-byte `num_buffers` field, which this crate leaves zeroed.
+    pub const SIZE: usize = 10;
+
+    pub const F_NEEDS_CSUM: u8 = VIRTIO_NET_HDR_F_NEEDS_CSUM;
+    pub const F_DATA_VALID: u8 = VIRTIO_NET_HDR_F_DATA_VALID;
+
+    pub const GSO_NONE: u8 = VIRTIO_NET_HDR_GSO_NONE;
+    pub const GSO_TCPV4: u8 = VIRTIO_NET_HDR_GSO_TCPV4;
+    pub const GSO_UDP: u8 = VIRTIO_NET_HDR_GSO_UDP;
+    pub const GSO_TCPV6: u8 = VIRTIO_NET_HDR_GSO_TCPV6;
+    pub const GSO_ECN: u8 = VIRTIO_NET_HDR_GSO_ECN;
+
+    /// Parses the header at the front of `packet`.
+    pub fn parse(packet: &[u8]) -> Option<VnetHdr> {
+        if packet.len() < VnetHdr::SIZE {
+            return None;
+        }
+        let u16_at = |i: usize| u16::from_ne_bytes([packet[i], packet[i + 1]]);
+        Some(VnetHdr {
+            flags: packet[0],
+            gso_type: packet[1],
+            hdr_len: u16_at(2),
+            gso_size: u16_at(4),
+            csum_start: u16_at(6),
+            csum_offset: u16_at(8),
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; VnetHdr::SIZE] {
+        let mut bytes = [0u8; VnetHdr::SIZE];
+        bytes[0] = self.flags;
+        bytes[1] = self.gso_type;
+        bytes[2..4].copy_from_slice(&self.hdr_len.to_ne_bytes());
+        bytes[4..6].copy_from_slice(&self.gso_size.to_ne_bytes());
+        bytes[6..8].copy_from_slice(&self.csum_start.to_ne_bytes());
+        bytes[8..10].copy_from_slice(&self.csum_offset.to_ne_bytes());
+        bytes
+    }
+}
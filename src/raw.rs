@@ -0,0 +1,4 @@
+//! The C ifreq layouts used internally, for issuing ioctls the crate
+//! doesn't wrap with `TunTap::ioctl_raw`.
+
+pub use c_interop::{in_ifreq, ioctl_flags_data, ioctl_ifindex_data, IFNAMSIZ, IFREQ_PAD};
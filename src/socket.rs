@@ -0,0 +1,49 @@
+use std::io;
+use std::mem;
+use libc::{c_int, SOCK_DGRAM, F_GETFD, F_SETFD, FD_CLOEXEC, socket, close, fcntl};
+
+/// Owns a control socket for the SIOC* ioctls and closes it when dropped,
+/// so no error path can leak it.
+pub struct SocketGuard(pub c_int);
+
+impl SocketGuard {
+    pub fn new(domain: c_int) -> io::Result<SocketGuard> {
+        let sock = unsafe { socket(domain, SOCK_DGRAM, 0) };
+        if sock < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let guard = SocketGuard(sock);
+        set_cloexec(sock, true)?;
+        Ok(guard)
+    }
+
+    /// Releases ownership without closing the socket.
+    pub fn into_raw(self) -> c_int {
+        let sock = self.0;
+        mem::forget(self);
+        sock
+    }
+}
+
+impl Drop for SocketGuard {
+    fn drop(&mut self) {
+        unsafe { close(self.0) };
+    }
+}
+
+/// Sets or clears FD_CLOEXEC, which closes `fd` when the process execs.
+pub fn set_cloexec(fd: c_int, cloexec: bool) -> io::Result<()> {
+    let flags = unsafe { fcntl(fd, F_GETFD) };
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let flags = if cloexec {
+        flags | FD_CLOEXEC
+    } else {
+        flags & !FD_CLOEXEC
+    };
+    if unsafe { fcntl(fd, F_SETFD, flags) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use std::io::{self, Read, Write};
+use error::TunTapError;
+use tuntap::TunTap;
+
+/// A device that can be put behind an `Arc` and split into reader and
+/// writer halves from any thread, instead of sharing a `&mut TunTap`
+/// behind a `Mutex`. Each half gets its own handle from `try_clone`.
+#[derive(Debug)]
+pub struct TunTapShared {
+    inner: TunTap,
+}
+
+/// The reading half of a TunTap, see `TunTap::split`.
+#[derive(Debug)]
+pub struct TunReader {
+    inner: TunTap,
+}
+
+/// The writing half of a TunTap, see `TunTap::split`.
+#[derive(Debug)]
+pub struct TunWriter {
+    inner: TunTap,
+}
+
+impl TunTapShared {
+    pub fn new(inner: TunTap) -> TunTapShared {
+        TunTapShared { inner }
+    }
+
+    /// Returns a new reader and writer for the device. Like `TunTap::split`
+    /// but leaves this handle in place, so it can be called again.
+    pub fn split(&self) -> Result<(TunReader, TunWriter), TunTapError> {
+        let reader = self.inner.try_clone()?;
+        let writer = self.inner.try_clone()?;
+        Ok((TunReader::new(reader), TunWriter::new(writer)))
+    }
+
+    /// Gives access to the configuration methods of the device.
+    pub fn get_ref(&self) -> &TunTap {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> TunTap {
+        self.inner
+    }
+}
+
+impl From<TunTap> for TunTapShared {
+    fn from(inner: TunTap) -> TunTapShared {
+        TunTapShared::new(inner)
+    }
+}
+
+impl TunReader {
+    pub(crate) fn new(inner: TunTap) -> TunReader {
+        TunReader { inner }
+    }
+
+    pub fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buffer)
+    }
+
+    pub fn read_packet(&mut self) -> io::Result<Vec<u8>> {
+        self.inner.read_packet()
+    }
+
+    /// Gives access to the configuration methods of the device.
+    pub fn get_ref(&self) -> &TunTap {
+        &self.inner
+    }
+}
+
+impl TunWriter {
+    pub(crate) fn new(inner: TunTap) -> TunWriter {
+        TunWriter { inner }
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.inner.write(data)
+    }
+
+    pub fn write_packet(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.inner.write_packet(data)
+    }
+
+    /// See `TunTap::flush`, writes aren't buffered.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Gives access to the configuration methods of the device.
+    pub fn get_ref(&self) -> &TunTap {
+        &self.inner
+    }
+}
+
+impl Read for TunReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Write for TunWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write_packet(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
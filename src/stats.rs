@@ -0,0 +1,89 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use error::TunTapError;
+
+/// Packet and byte counters of an interface, as reported by the kernel.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct InterfaceStats {
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_dropped: u64,
+    pub tx_dropped: u64,
+}
+
+impl InterfaceStats {
+    /// Reads the counters from a sysfs-style directory holding one file per
+    /// counter, e.g. `/sys/class/net/tun0/statistics`.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<InterfaceStats, TunTapError> {
+        let dir = dir.as_ref();
+        let read = |name: &str| -> Result<u64, TunTapError> {
+            let contents = fs::read_to_string(dir.join(name))?;
+            contents.trim().parse().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData,
+                               format!("Invalid counter in {}", name)).into()
+            })
+        };
+
+        Ok(InterfaceStats {
+            rx_packets: read("rx_packets")?,
+            tx_packets: read("tx_packets")?,
+            rx_bytes: read("rx_bytes")?,
+            tx_bytes: read("tx_bytes")?,
+            rx_dropped: read("rx_dropped")?,
+            tx_dropped: read("tx_dropped")?,
+        })
+    }
+}
+
+/// Duplex mode of a link, see `LinkInfo`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Duplex {
+    Half,
+    Full,
+}
+
+/// Link speed, duplex and carrier of an interface. Each is `None` when the
+/// kernel doesn't know it, which for TUN and TAP devices is usually the
+/// case while they are down.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct LinkInfo {
+    /// Speed in Mbit/s.
+    pub speed: Option<u32>,
+    pub duplex: Option<Duplex>,
+    pub carrier: Option<bool>,
+}
+
+impl LinkInfo {
+    /// Reads the link state from a sysfs-style interface directory, e.g.
+    /// `/sys/class/net/tun0`.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<LinkInfo, TunTapError> {
+        let dir = dir.as_ref();
+        // sysfs fails reads with EINVAL for values the driver doesn't have
+        let read = |name: &str| -> Result<Option<String>, TunTapError> {
+            match fs::read_to_string(dir.join(name)) {
+                Ok(contents) => Ok(Some(contents.trim().to_string())),
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound ||
+                                err.kind() == io::ErrorKind::InvalidInput => Ok(None),
+                Err(err) => Err(err.into()),
+            }
+        };
+
+        Ok(LinkInfo {
+            // Unknown speeds read as -1
+            speed: read("speed")?.and_then(|speed| speed.parse().ok()),
+            duplex: match read("duplex")?.as_deref() {
+                Some("half") => Some(Duplex::Half),
+                Some("full") => Some(Duplex::Full),
+                _ => None,
+            },
+            carrier: match read("carrier")?.as_deref() {
+                Some("0") => Some(false),
+                Some("1") => Some(true),
+                _ => None,
+            },
+        })
+    }
+}
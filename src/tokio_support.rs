@@ -0,0 +1,85 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::io::unix::AsyncFd;
+use error::TunTapError;
+use tuntap::{TunTap, TunTapType};
+
+/// A TunTap registered with the tokio reactor.
+///
+/// Configuration methods are still reachable through `get_ref`.
+pub struct AsyncTunTap {
+    inner: AsyncFd<TunTap>,
+}
+
+impl AsyncTunTap {
+    pub fn new(typ: TunTapType, name: &str) -> Result<AsyncTunTap, TunTapError> {
+        AsyncTunTap::from_tuntap(TunTap::new(typ, name)?)
+    }
+
+    /// Switches `tuntap` into non-blocking mode and registers it with the
+    /// current tokio runtime.
+    pub fn from_tuntap(tuntap: TunTap) -> Result<AsyncTunTap, TunTapError> {
+        tuntap.set_nonblocking(true)?;
+        Ok(AsyncTunTap {
+            inner: AsyncFd::new(tuntap)?,
+        })
+    }
+
+    pub fn get_ref(&self) -> &TunTap {
+        self.inner.get_ref()
+    }
+
+    pub fn into_inner(self) -> TunTap {
+        self.inner.into_inner()
+    }
+}
+
+impl AsyncRead for AsyncTunTap {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf)
+                 -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = match self.inner.poll_read_ready(cx) {
+                Poll::Ready(guard) => guard?,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            let unfilled = buf.initialize_unfilled();
+            match guard.try_io(|inner| inner.get_ref().read_fd(unfilled)) {
+                Ok(Ok(len)) => {
+                    buf.advance(len);
+                    return Poll::Ready(Ok(()));
+                }
+                Ok(Err(err)) => return Poll::Ready(Err(err)),
+                // Readiness was stale, wait for the next event
+                Err(_) => continue,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for AsyncTunTap {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8])
+                  -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = match self.inner.poll_write_ready(cx) {
+                Poll::Ready(guard) => guard?,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            match guard.try_io(|inner| inner.get_ref().write_fd(buf)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -1,48 +1,456 @@
 use std::ffi::CString;
 use std::fmt;
+use std::fs;
 use std::fs::File;
 use std::fs::OpenOptions;
-use std::io::{Read, Write};
+use std::io::{IoSlice, IoSliceMut, Read, Write};
 use std::io;
 use std::mem;
+use std::ptr;
+use std::mem::MaybeUninit;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
-use std::os::unix::prelude::AsRawFd;
-use std::path::Path;
-use libc::{c_int, c_char, AF_INET, AF_INET6, SOCK_DGRAM, socket, ioctl, close,
-           sockaddr_in, sa_family_t, sockaddr, in_addr, in6_addr};
+use std::os::unix::prelude::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use libc::{c_int, c_char, c_short, c_ulong, AF_INET, AF_INET6, ioctl, close,
+           fcntl, F_GETFL, F_SETFL, O_ACCMODE, O_CLOEXEC, O_NONBLOCK, O_RDONLY, O_WRONLY, poll, pollfd, POLLIN, read, c_void,
+           sockaddr_in, sa_family_t, sockaddr, in_addr, in6_addr, uid_t, gid_t, sock_fprog,
+           sock_filter, mknod, makedev, S_IFCHR, ENODEV, ENOENT, EINVAL, ENOSYS, EOPNOTSUPP,
+           iovec, preadv2, RWF_NOWAIT};
+use batch::PacketBatch;
+use builder::TunTapConfig;
 use c_interop::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use error::TunTapError;
+use ethernet::EthernetFrame;
+use filter::SockFilter;
+use ip::{detect_ip_version, IpVersion};
+use socket::{self, SocketGuard};
+use split::{TunReader, TunWriter};
+use offload::{OffloadFlags, VnetHdr};
+use stats::{InterfaceStats, LinkInfo};
 
-const DEVICE_PATH: &'static str = "/dev/net/tun";
+pub(crate) const DEVICE_PATH: &str = "/dev/net/tun";
 
-const MTU_SIZE: usize = 1500;
+const IF_INET6_PATH: &str = "/proc/net/if_inet6";
+
+pub(crate) const SYS_CLASS_NET: &str = "/sys/class/net";
+
+/// The longest interface name the kernel accepts, in bytes.
+pub const MAX_NAME_LEN: usize = IFNAMSIZ - 1;
 
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum TunTapType {
     Tun,
     Tap,
 }
 
+/// Whether a device is opened for reading packets, writing them, or both,
+/// see `TunTapBuilder::open_mode`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum OpenMode {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+}
+
+/// The queues of a multi-queue device, see `TunTap::queue_info`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct QueueInfo {
+    /// Whether each queue is enabled, in the order they were attached with
+    /// `add_queue` on any queue of the device. Closed queues are left out.
+    pub enabled: Vec<bool>,
+    /// Queues the kernel currently delivers packets to, counting enabled
+    /// queues other processes attached as well.
+    pub active: usize,
+}
+
+/// The `struct tun_pi` header used when a device is created without IFF_NO_PI.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct PacketInfo {
+    /// TUN_PKT_STRIP is set by the kernel if the packet was truncated.
+    pub flags: u16,
+    /// EtherType of the packet, e.g. 0x0800 for IPv4 or 0x86dd for IPv6.
+    pub proto: u16,
+}
+
+impl PacketInfo {
+    pub const SIZE: usize = 4;
+
+    /// Parses the header at the front of `packet`.
+    pub fn parse(packet: &[u8]) -> Option<PacketInfo> {
+        if packet.len() < PacketInfo::SIZE {
+            return None;
+        }
+        Some(PacketInfo::from_bytes([packet[0], packet[1], packet[2], packet[3]]))
+    }
+
+    pub fn from_bytes(bytes: [u8; 4]) -> PacketInfo {
+        PacketInfo {
+            flags: u16::from_ne_bytes([bytes[0], bytes[1]]),
+            proto: u16::from_be_bytes([bytes[2], bytes[3]]),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let flags = self.flags.to_ne_bytes();
+        let proto = self.proto.to_be_bytes();
+        [flags[0], flags[1], proto[0], proto[1]]
+    }
+}
+
+/// A tun or tap device.
+///
+/// TunTap is `Send` and `Sync`. Configuration methods take `&self` and may
+/// be called from any thread, but nothing orders them against each other:
+/// two threads changing the MTU race just like two `ip link` commands would.
+/// Reads and writes take `&mut self`; the kernel copes with one thread
+/// reading while another writes, so give each thread its own handle from
+/// `try_clone`.
 pub struct TunTap {
     pub file: File,
+    // Where the device node was opened, reused by add_queue
+    device_path: PathBuf,
     if_name: [u8; IFNAMSIZ],
+    if_flags: c_short,
+    mtu: AtomicUsize,
+    vnet_hdr_len: AtomicUsize,
+    // OffloadFlags bits last set with set_offload
+    offload: AtomicU32,
+    // poll() timeout for reads in milliseconds, -1 to block
+    read_timeout: AtomicI32,
+    // Access mode of the file, to fail reads or writes it doesn't allow early
+    mode: OpenMode,
+    // Nanoseconds after `created` of the last packet I/O, plus one
+    created: Instant,
+    last_activity: AtomicU64,
+    down_on_drop: bool,
+    // Whether this queue is enabled, shared with clones of the handle
+    queue: Arc<AtomicBool>,
+    // Every queue attached with add_queue, starting from the first handle
+    queues: Arc<Mutex<Vec<Weak<AtomicBool>>>>,
+    // Control sockets for the SIOC* ioctls
+    sock: SocketGuard,
+    sock6: AtomicI32,
 }
 
+// Fails to compile if a field ever makes TunTap lose Send or Sync
+fn _assert_send_sync() {
+    fn assert<T: Send + Sync>() {}
+    assert::<TunTap>();
+}
+
+// The MTU is the last value read or set, Debug doesn't query the kernel
 impl fmt::Debug for TunTap {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Tun({})", self.get_name())
+        f.debug_struct("TunTap")
+            .field("name", &self.get_name())
+            .field("type", &self.get_type())
+            .field("fd", &self.file.as_raw_fd())
+            .field("mtu", &self.mtu.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+impl fmt::Display for TunTap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.get_name())
+    }
+}
+
+impl Drop for TunTap {
+    fn drop(&mut self) {
+        if self.down_on_drop {
+            // Errors can't be reported from here
+            let _ = self.bring_down();
+        }
+        let sock6 = self.sock6.load(Ordering::Relaxed);
+        if sock6 >= 0 {
+            unsafe { close(sock6) };
+        }
+    }
+}
+
+impl AsRawFd for TunTap {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for TunTap {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+// Each call reads or writes exactly one packet, like the inherent methods
+impl Read for TunTap {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        TunTap::read(self, buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        TunTap::read_vectored(self, bufs)
+    }
+}
+
+impl Write for TunTap {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_packet(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        TunTap::write_vectored(self, bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        TunTap::flush(self)
     }
 }
 
 impl TunTap {
-    pub fn new(typ: TunTapType, name: &str) -> TunTap {
-        let (file, if_name) = TunTap::create_if(typ, name);
-        TunTap {
-            file: file,
-            if_name: if_name,
+    /// Creates a device named `name` and brings it up.
+    ///
+    /// If `name` is empty or contains `%d`, the kernel picks the next free
+    /// name (e.g. `tun0`, or `vpn3` for `vpn%d`); `get_name` returns the name
+    /// that was actually assigned.
+    pub fn new(typ: TunTapType, name: &str) -> Result<TunTap, TunTapError> {
+        TunTap::with_flags(Path::new(DEVICE_PATH), typ, name, IFF_NO_PI)
+    }
+
+    /// Creates a TUN device with a kernel-assigned name, see `get_name`.
+    pub fn tun() -> Result<TunTap, TunTapError> {
+        TunTap::new(TunTapType::Tun, "")
+    }
+
+    /// Creates a TAP device with a kernel-assigned name, see `get_name`.
+    pub fn tap() -> Result<TunTap, TunTapError> {
+        TunTap::new(TunTapType::Tap, "")
+    }
+
+    /// Creates `/dev/net/tun` if it doesn't exist, as in containers that
+    /// don't provide it. Needs root; the tun driver still has to be
+    /// available for the node to open.
+    pub fn ensure_device_node() -> Result<(), TunTapError> {
+        let path = Path::new(DEVICE_PATH);
+        if path.exists() {
+            return Ok(());
+        }
+
+        fs::create_dir_all("/dev/net")?;
+        let path_c = CString::new(DEVICE_PATH).unwrap();
+        // The misc device the tun driver registers
+        let res = unsafe { mknod(path_c.as_ptr(), S_IFCHR | 0o666, makedev(10, 200)) };
+        if res < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        // mknod is subject to the umask, but everyone may open the node
+        fs::set_permissions(path, fs::Permissions::from_mode(0o666))?;
+        Ok(())
+    }
+
+    /// Like `new`, but opens the tun device node at `path` instead of
+    /// `/dev/net/tun`, e.g. when a container mounts it elsewhere.
+    pub fn with_device_path<P: AsRef<Path>>(path: P, typ: TunTapType, name: &str)
+                                            -> Result<TunTap, TunTapError> {
+        TunTap::with_flags(path.as_ref(), typ, name, IFF_NO_PI)
+    }
+
+    /// Creates a multi-queue device. Additional queues can be attached with `add_queue`,
+    /// e.g. one per worker thread, and the kernel spreads packets across them.
+    pub fn new_multi_queue(typ: TunTapType, name: &str) -> Result<TunTap, TunTapError> {
+        TunTap::with_flags(Path::new(DEVICE_PATH), typ, name, IFF_NO_PI | IFF_MULTI_QUEUE)
+    }
+
+    /// Creates a device that keeps the 4-byte packet information header
+    /// (`struct tun_pi`) in front of every packet. See `read_with_pi` and
+    /// `write_with_pi`.
+    pub fn new_with_packet_info(typ: TunTapType, name: &str) -> Result<TunTap, TunTapError> {
+        TunTap::with_flags(Path::new(DEVICE_PATH), typ, name, 0)
+    }
+
+    fn with_flags(path: &Path, typ: TunTapType, name: &str, flags: c_short)
+                  -> Result<TunTap, TunTapError> {
+        let tuntap = TunTap::open_with_flags(path, typ, name, flags, OpenMode::ReadWrite)?;
+        tuntap.bring_up()?;
+        Ok(tuntap)
+    }
+
+    // Creates the device but leaves it down
+    pub(crate) fn open_with_flags(path: &Path, typ: TunTapType, name: &str, flags: c_short,
+                                  mode: OpenMode) -> Result<TunTap, TunTapError> {
+        let if_flags = flags | match typ {
+            TunTapType::Tun => IFF_TUN,
+            TunTapType::Tap => IFF_TAP,
+        };
+        let (file, if_name) = TunTap::create_if(path, name, if_flags, mode)?;
+        TunTap::from_parts(file, path.to_path_buf(), if_name, if_flags)
+    }
+
+    fn from_parts(file: File, device_path: PathBuf, if_name: [u8; IFNAMSIZ], if_flags: c_short)
+                  -> Result<TunTap, TunTapError> {
+        let status = unsafe { fcntl(file.as_raw_fd(), F_GETFL) };
+        if status < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let mode = match status & O_ACCMODE {
+            O_RDONLY => OpenMode::ReadOnly,
+            O_WRONLY => OpenMode::WriteOnly,
+            _ => OpenMode::ReadWrite,
+        };
+        let queue = Arc::new(AtomicBool::new(true));
+        let tuntap = TunTap {
+            file,
+            device_path,
+            if_name,
+            if_flags,
+            mtu: AtomicUsize::new(0),
+            vnet_hdr_len: AtomicUsize::new(VnetHdr::SIZE),
+            offload: AtomicU32::new(0),
+            read_timeout: AtomicI32::new(-1),
+            mode,
+            created: Instant::now(),
+            last_activity: AtomicU64::new(0),
+            down_on_drop: false,
+            queues: Arc::new(Mutex::new(vec![Arc::downgrade(&queue)])),
+            queue,
+            sock: SocketGuard::new(AF_INET)?,
+            sock6: AtomicI32::new(-1),
+        };
+        tuntap.get_mtu()?;
+        if if_flags & IFF_VNET_HDR != 0 {
+            tuntap.get_vnet_hdr_size()?;
+        }
+        Ok(tuntap)
+    }
+
+    /// Wraps a tun file descriptor that was opened and attached elsewhere,
+    /// e.g. by a privileged helper that passed it over a unix socket.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open `/dev/net/tun` descriptor attached to the
+    /// interface `if_name`. The returned TunTap takes ownership of it.
+    ///
+    /// The node `fd` was opened from isn't known, so `add_queue` opens
+    /// `/dev/net/tun` even if the descriptor came from another path.
+    pub unsafe fn from_raw_fd(fd: RawFd, if_name: &str) -> Result<TunTap, TunTapError> {
+        let if_name = TunTap::ifr_name(if_name)?;
+        let file = File::from_raw_fd(fd);
+        let req = TunTap::get_iff(&file)?;
+        TunTap::from_parts(file, PathBuf::from(DEVICE_PATH), if_name, req.ifr_flags)
+    }
+
+    /// Creates a device from `config`, see `TunTapConfig`.
+    pub fn from_config(config: &TunTapConfig) -> Result<TunTap, TunTapError> {
+        config.build()
+    }
+
+    /// Like `new`, but creates the device on a `/dev/net/tun` file the
+    /// caller opened, e.g. to choose the open flags.
+    ///
+    /// `add_queue` always opens `/dev/net/tun` for the new queue, not the
+    /// node `file` came from; use `TunTapBuilder::device_path` to create a
+    /// multi-queue device on another node.
+    pub fn from_file(file: File, typ: TunTapType, name: &str) -> Result<TunTap, TunTapError> {
+        let if_flags = IFF_NO_PI | match typ {
+            TunTapType::Tun => IFF_TUN,
+            TunTapType::Tap => IFF_TAP,
+        };
+        let if_name = TunTap::set_iff(&file, TunTap::ifr_name(name)?, if_flags)?;
+        let tuntap = TunTap::from_parts(file, PathBuf::from(DEVICE_PATH), if_name, if_flags)?;
+        tuntap.bring_up()?;
+        Ok(tuntap)
+    }
+
+    /// Renames the interface. The kernel only renames interfaces that are
+    /// down, so an interface that is up is brought down for the rename and
+    /// up again afterwards. `new_name` may contain `%d` like in `new`.
+    ///
+    /// Other handles to the device, from `try_clone` or `add_queue`, keep
+    /// using the old name.
+    pub fn rename(&mut self, new_name: &str) -> Result<(), TunTapError> {
+        let mut req = ioctl_newname {
+            ifr_name: self.if_name,
+            ifr_newname: TunTap::ifr_name(new_name)?,
+            ifr_pad: [0; IFREQ_PAD],
+        };
+
+        let was_up = self.is_up()?;
+        if was_up {
+            self.bring_down()?;
+        }
+        let res = unsafe { ioctl(self.sock.0, SIOCSIFNAME, &mut req) };
+        let renamed = if res < 0 {
+            Err(TunTapError::ioctl("SIOCSIFNAME"))
+        } else {
+            self.refresh_name()
+        };
+        if was_up {
+            self.bring_up()?;
+        }
+        renamed
+    }
+
+    // Reads back the name the kernel settled on, e.g. after a %d rename
+    fn refresh_name(&mut self) -> Result<(), TunTapError> {
+        self.if_name = TunTap::get_iff(&self.file)?.ifr_name;
+        Ok(())
+    }
+
+    /// Returns the flags the device was created with (IFF_TUN or IFF_TAP,
+    /// IFF_NO_PI, IFF_MULTI_QUEUE, ...) as the kernel reports them, e.g. to
+    /// check a device passed in through `from_raw_fd`. See `decode_iff`.
+    pub fn query_iff(&self) -> Result<i32, TunTapError> {
+        Ok(TunTap::get_iff(&self.file)?.ifr_flags as u16 as i32)
+    }
+
+    /// Splits flags from `query_iff` into the device type and whether
+    /// packets carry packet information.
+    pub fn decode_iff(flags: i32) -> (TunTapType, bool) {
+        let typ = if flags & IFF_TAP as i32 != 0 {
+            TunTapType::Tap
+        } else {
+            TunTapType::Tun
+        };
+        (typ, flags & IFF_NO_PI as i32 == 0)
+    }
+
+    fn get_iff(file: &File) -> Result<ioctl_flags_data, TunTapError> {
+        let mut req = ioctl_flags_data {
+            ifr_name: [0; IFNAMSIZ],
+            ifr_flags: 0,
+            ifr_pad: [0; IFREQ_PAD],
+        };
+        let res = unsafe { ioctl(file.as_raw_fd(), TUNGETIFF, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("TUNGETIFF"));
+        }
+        Ok(req)
+    }
+
+    /// Whether the device was created with packet info (without IFF_NO_PI),
+    /// so each packet read or written starts with a `PacketInfo` header.
+    pub fn has_packet_info(&self) -> bool {
+        self.if_flags & IFF_NO_PI == 0
+    }
+
+    pub fn get_type(&self) -> TunTapType {
+        if self.if_flags & IFF_TAP != 0 {
+            TunTapType::Tap
+        } else {
+            TunTapType::Tun
         }
     }
 
+    /// Returns the interface name as assigned by the kernel.
     pub fn get_name(&self) -> String {
         let nul_pos = match self.if_name.iter().position(|x| *x == 0) {
             Some(p) => p,
@@ -51,179 +459,1631 @@ impl TunTap {
         CString::new(&self.if_name[..nul_pos]).unwrap().into_string().unwrap()
     }
 
-    fn create_if(typ: TunTapType, name: &str) -> (File, [u8; IFNAMSIZ]) {
-        let name_c = &CString::new(name).unwrap();
+    // An empty name is fine and lets the kernel pick one. Otherwise this
+    // mirrors the kernel's dev_valid_name(), plus a ban on control characters.
+    fn ifr_name(name: &str) -> Result<[u8; IFNAMSIZ], TunTapError> {
+        if name == "." || name == ".." ||
+           name.chars().any(|c| c == '/' || c == ':' || c.is_whitespace() || c.is_control()) {
+            return Err(TunTapError::InvalidName);
+        }
+        let name_c = &CString::new(name).map_err(|_| TunTapError::InvalidName)?;
         let name_slice = name_c.as_bytes_with_nul();
         if name_slice.len() > IFNAMSIZ {
-            panic!("Interface name too long, max length is {}", IFNAMSIZ - 1);
+            return Err(TunTapError::NameTooLong { max: MAX_NAME_LEN });
         }
 
-        let path = Path::new(DEVICE_PATH);
-        let file = match OpenOptions::new().read(true).write(true).open(&path) {
-            Err(why) => panic!("Couldn't open tun device '{}': {:?}", path.display(), why),
-            Ok(file) => file,
-        };
+        let mut buffer = [0u8; IFNAMSIZ];
+        buffer[..name_slice.len()].clone_from_slice(name_slice);
+        Ok(buffer)
+    }
+
+    fn create_if(path: &Path, name: &str, flags: c_short, mode: OpenMode)
+                 -> Result<(File, [u8; IFNAMSIZ]), TunTapError> {
+        TunTap::attach(path, TunTap::ifr_name(name)?, flags, mode)
+    }
 
+    fn attach(path: &Path, if_name: [u8; IFNAMSIZ], flags: c_short, mode: OpenMode)
+              -> Result<(File, [u8; IFNAMSIZ]), TunTapError> {
+        let file = OpenOptions::new()
+            .read(mode != OpenMode::WriteOnly)
+            .write(mode != OpenMode::ReadOnly)
+            .custom_flags(O_CLOEXEC)
+            .open(path)
+            .map_err(|err| match err.raw_os_error() {
+                Some(ENOENT) | Some(ENODEV) => {
+                    TunTapError::DeviceNodeMissing { path: path.to_path_buf(), source: err }
+                }
+                _ => TunTapError::DeviceOpen(err),
+            })?;
+        let if_name = TunTap::set_iff(&file, if_name, flags)?;
+        Ok((file, if_name))
+    }
+
+    fn set_iff(file: &File, if_name: [u8; IFNAMSIZ], flags: c_short)
+               -> Result<[u8; IFNAMSIZ], TunTapError> {
         let mut req = ioctl_flags_data {
-            ifr_name: {
-                let mut buffer = [0u8; IFNAMSIZ];
-                buffer[..name_slice.len()].clone_from_slice(name_slice);
-                buffer
-            },
-            ifr_flags: match typ {
-                TunTapType::Tun => IFF_TUN | IFF_NO_PI,
-                TunTapType::Tap => IFF_TAP | IFF_NO_PI,
-            },
+            ifr_name: if_name,
+            ifr_flags: flags,
+            ifr_pad: [0; IFREQ_PAD],
         };
 
         let res = unsafe { ioctl(file.as_raw_fd(), TUNSETIFF, &mut req) };
         if res < 0 {
-            panic!("{}", io::Error::last_os_error());
+            return Err(TunTapError::ioctl("TUNSETIFF"));
+        }
+        Ok(req.ifr_name)
+    }
+
+    /// Attaches another queue to a device created with `new_multi_queue`.
+    ///
+    /// The returned TunTap has its own file descriptor and can be read and
+    /// written independently of this one, e.g. registered with epoll through
+    /// `AsRawFd` next to the other queues. The kernel spreads packets over
+    /// the queues by flow, and the device goes away with its last queue.
+    pub fn add_queue(&self) -> Result<TunTap, TunTapError> {
+        let (file, if_name) = TunTap::attach(&self.device_path, self.if_name, self.if_flags,
+                                              self.mode)?;
+        let mut tuntap = TunTap::from_parts(file, self.device_path.clone(), if_name,
+                                            self.if_flags)?;
+        // Offloads apply to the whole device, but can't be read back
+        tuntap.offload.store(self.offload.load(Ordering::Relaxed), Ordering::Relaxed);
+        let mut queues = self.queues.lock().unwrap();
+        queues.retain(|queue| queue.strong_count() > 0);
+        queues.push(Arc::downgrade(&tuntap.queue));
+        tuntap.queues = self.queues.clone();
+        Ok(tuntap)
+    }
+
+    /// Returns a second handle to the same queue, backed by a duplicated
+    /// file descriptor, e.g. to read and write from separate threads.
+    ///
+    /// Unlike `add_queue` this doesn't attach a new queue: packets are
+    /// delivered to whichever handle reads first. The clone never brings the
+    /// device down when dropped.
+    pub fn try_clone(&self) -> Result<TunTap, TunTapError> {
+        let file = self.file.try_clone()?;
+        let mut tuntap = TunTap::from_parts(file, self.device_path.clone(), self.if_name,
+                                            self.if_flags)?;
+        tuntap.read_timeout.store(self.read_timeout.load(Ordering::Relaxed), Ordering::Relaxed);
+        tuntap.offload.store(self.offload.load(Ordering::Relaxed), Ordering::Relaxed);
+        tuntap.queue = self.queue.clone();
+        tuntap.queues = self.queues.clone();
+        Ok(tuntap)
+    }
+
+    /// Splits the device into halves that can be moved to a reader and a
+    /// writer thread, each with its own handle from `try_clone`. Dropping
+    /// the reader closes the original handle, see `set_down_on_drop`.
+    /// `TunTapShared` hands out halves repeatedly from a shared handle.
+    pub fn split(self) -> Result<(TunReader, TunWriter), TunTapError> {
+        let writer = self.try_clone()?;
+        Ok((TunReader::new(self), TunWriter::new(writer)))
+    }
+
+    /// Enables or disables this queue of a multi-queue device. The kernel
+    /// doesn't deliver packets to disabled queues.
+    pub fn set_queue_enabled(&self, enabled: bool) -> Result<(), TunTapError> {
+        let mut req = ioctl_flags_data {
+            ifr_name: [0; IFNAMSIZ],
+            ifr_flags: if enabled { IFF_ATTACH_QUEUE } else { IFF_DETACH_QUEUE },
+            ifr_pad: [0; IFREQ_PAD],
+        };
+        let res = unsafe { ioctl(self.file.as_raw_fd(), TUNSETQUEUE, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("TUNSETQUEUE"));
+        }
+        self.queue.store(enabled, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Reports which of the queues attached with `add_queue` are enabled,
+    /// and how many queues the kernel spreads packets over. The kernel
+    /// doesn't report disabled queues, so those are only known for queues
+    /// attached from this process.
+    pub fn queue_info(&self) -> Result<QueueInfo, TunTapError> {
+        let enabled = self.queues.lock().unwrap().iter()
+            .filter_map(Weak::upgrade)
+            .map(|queue| queue.load(Ordering::Relaxed))
+            .collect();
+        // The kernel keeps one rx-N directory per queue it delivers to
+        let mut active = 0;
+        for entry in fs::read_dir(self.sysfs_path("queues"))? {
+            if entry?.file_name().to_string_lossy().starts_with("rx-") {
+                active += 1;
+            }
         }
+        Ok(QueueInfo { enabled, active })
+    }
 
-        TunTap::up(req.ifr_name);
+    // The AF_INET6 socket is only opened on first use, so that hosts with
+    // IPv6 disabled can still use everything else.
+    fn inet6_socket(&self) -> Result<c_int, TunTapError> {
+        let sock = self.sock6.load(Ordering::Acquire);
+        if sock >= 0 {
+            return Ok(sock);
+        }
 
-        (file, req.ifr_name)
+        let sock = SocketGuard::new(AF_INET6)?;
+        match self.sock6.compare_exchange(-1, sock.0, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => Ok(sock.into_raw()),
+            // Another thread got there first, the guard closes ours
+            Err(other) => Ok(other),
+        }
     }
 
-    fn create_socket(sock_type: i32) -> c_int {
-        let sock = unsafe { socket(sock_type, SOCK_DGRAM, 0) };
-        if sock < 0 {
-            panic!("{}", io::Error::last_os_error());
+    /// Returns when this handle last read or wrote a packet, or `None` if it
+    /// hasn't yet, e.g. to tear down tunnels that have gone idle. Handles
+    /// from `try_clone` and `add_queue` keep track of their own activity.
+    pub fn last_activity(&self) -> Option<Instant> {
+        match self.last_activity.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(self.created + Duration::from_nanos(nanos - 1)),
         }
-        sock
     }
 
-    fn up(if_name: [u8; IFNAMSIZ]) {
-        let sock = TunTap::create_socket(AF_INET);
+    /// Returns the interface name as the NUL-padded `ifr_name` of an ifreq,
+    /// for use with `ioctl_raw`.
+    pub fn ifr_name_bytes(&self) -> [u8; IFNAMSIZ] {
+        self.if_name
+    }
+
+    /// Issues `request` with `arg` on the AF_INET control socket, for
+    /// SIOC* calls the crate has no method for. See the `raw` module for
+    /// the ifreq layouts. Requests on the device itself, like the TUN*
+    /// ones, go to `as_raw_fd()` instead.
+    ///
+    /// # Safety
+    ///
+    /// `arg` must be what the kernel expects for `request`, in size and
+    /// layout; the kernel reads and writes through it unchecked.
+    pub unsafe fn ioctl_raw<T>(&self, request: c_ulong, arg: &mut T) -> Result<(), TunTapError> {
+        if ioctl(self.sock.0, request, arg as *mut T) < 0 {
+            return Err(TunTapError::ioctl("ioctl_raw"));
+        }
+        Ok(())
+    }
 
+    /// Returns the interface flags (`IFF_*` constants from the `flags` module).
+    pub fn get_flags(&self) -> Result<i32, TunTapError> {
         let mut req = ioctl_flags_data {
-            ifr_name: if_name,
+            ifr_name: self.if_name,
             ifr_flags: 0,
+            ifr_pad: [0; IFREQ_PAD],
         };
 
+        let res = unsafe { ioctl(self.sock.0, SIOCGIFFLAGS, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("SIOCGIFFLAGS"));
+        }
+        Ok(req.ifr_flags as u16 as i32)
+    }
+
+    /// Replaces the interface flags. Only the lower 16 bits are used.
+    pub fn set_flags(&self, flags: i32) -> Result<(), TunTapError> {
+        let mut req = ioctl_flags_data {
+            ifr_name: self.if_name,
+            ifr_flags: flags as c_short,
+            ifr_pad: [0; IFREQ_PAD],
+        };
 
-        let res = unsafe { ioctl(sock, SIOCGIFFLAGS, &mut req) };
+        let res = unsafe { ioctl(self.sock.0, SIOCSIFFLAGS, &mut req) };
         if res < 0 {
-            unsafe { close(sock) };
-            panic!("{}", io::Error::last_os_error());
+            return Err(TunTapError::ioctl("SIOCSIFFLAGS"));
         }
+        Ok(())
+    }
 
-        if req.ifr_flags & IFF_UP & IFF_RUNNING != 0 {
+    /// Sets IFF_UP and IFF_RUNNING on the interface.
+    pub fn bring_up(&self) -> Result<(), TunTapError> {
+        let flags = self.get_flags()?;
+        let up = (IFF_UP | IFF_RUNNING) as i32;
+        if flags & up == up {
             // Already up
-            return;
+            return Ok(());
         }
+        self.set_flags(flags | up)
+    }
 
-        req.ifr_flags |= IFF_UP | IFF_RUNNING;
+    /// Clears IFF_UP and IFF_RUNNING on the interface, e.g. before reconfiguring it.
+    pub fn bring_down(&self) -> Result<(), TunTapError> {
+        let flags = self.get_flags()?;
+        self.set_flags(flags & !((IFF_UP | IFF_RUNNING) as i32))
+    }
 
-        let res = unsafe { ioctl(sock, SIOCSIFFLAGS, &mut req) };
-        if res < 0 {
-            unsafe { close(sock) };
-            panic!("{}", io::Error::last_os_error());
+    /// Whether the interface is administratively up.
+    pub fn is_up(&self) -> Result<bool, TunTapError> {
+        Ok(self.get_flags()? & IFF_UP as i32 != 0)
+    }
+
+    /// Whether the interface is operational, i.e. up and with a carrier.
+    pub fn is_running(&self) -> Result<bool, TunTapError> {
+        Ok(self.get_flags()? & IFF_RUNNING as i32 != 0)
+    }
+
+    /// Makes a TAP device accept frames for any destination MAC address.
+    pub fn set_promiscuous(&self, on: bool) -> Result<(), TunTapError> {
+        if self.get_type() != TunTapType::Tap {
+            return Err(TunTapError::RequiresTap);
+        }
+
+        let flags = self.get_flags()?;
+        if on {
+            self.set_flags(flags | IFF_PROMISC as i32)
+        } else {
+            self.set_flags(flags & !(IFF_PROMISC as i32))
         }
-        unsafe { close(sock) };
     }
 
-    pub fn add_ipv4_addr(&self, addr: Ipv4Addr) {
-        let octets = addr.octets();
-        let sock = TunTap::create_socket(AF_INET);
-        let sock_addr = sockaddr_in {
-            sin_family: AF_INET as sa_family_t,
-            sin_port: 0,
-            sin_addr: in_addr {
-                s_addr: (((octets[0] as u32) << 24) |
-                         ((octets[1] as u32) << 16) |
-                         ((octets[2] as u32) <<  8) |
-                          (octets[3] as u32)).to_be(),
-            },
-            sin_zero: [0, 0, 0, 0, 0, 0, 0, 0],
-        };
+    pub fn is_promiscuous(&self) -> Result<bool, TunTapError> {
+        Ok(self.get_flags()? & IFF_PROMISC as i32 != 0)
+    }
+
+    /// Sets or clears IFF_NOARP. The kernel already sets it on new TUN
+    /// devices, which have no link layer to resolve addresses on. Doesn't
+    /// touch the flags, and so needs no privileges, if they already match.
+    pub fn set_noarp(&self, on: bool) -> Result<(), TunTapError> {
+        let flags = self.get_flags()?;
+        if (flags & IFF_NOARP as i32 != 0) == on {
+            return Ok(());
+        }
+        if on {
+            self.set_flags(flags | IFF_NOARP as i32)
+        } else {
+            self.set_flags(flags & !(IFF_NOARP as i32))
+        }
+    }
+
+    pub fn is_noarp(&self) -> Result<bool, TunTapError> {
+        Ok(self.get_flags()? & IFF_NOARP as i32 != 0)
+    }
+
+    /// Returns the underlying file without closing it, e.g. to hand the
+    /// device over to other code. Unlike dropping, this leaves the interface
+    /// as it is even with `set_down_on_drop`.
+    pub fn into_inner(self) -> File {
+        let this = mem::ManuallyDrop::new(self);
+        let sock6 = this.sock6.load(Ordering::Relaxed);
+        if sock6 >= 0 {
+            unsafe { close(sock6) };
+        }
+        // `this` is never used again, so each field is moved out once
+        unsafe {
+            drop(ptr::read(&this.device_path));
+            drop(ptr::read(&this.queue));
+            drop(ptr::read(&this.queues));
+            drop(ptr::read(&this.sock));
+            ptr::read(&this.file)
+        }
+    }
+
+    /// Closes the device, reporting the errors dropping it would ignore.
+    /// With `set_down_on_drop` the interface is brought down first.
+    pub fn close(self) -> io::Result<()> {
+        if self.down_on_drop {
+            self.bring_down()?;
+        }
+        let fd = self.into_inner().into_raw_fd();
+        if unsafe { close(fd) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Brings the interface down when this TunTap is dropped.
+    ///
+    /// Non-persistent devices are removed by the kernel anyway, so this is
+    /// mostly useful together with `set_persistent(true)`.
+    pub fn set_down_on_drop(&mut self, down: bool) {
+        self.down_on_drop = down;
+    }
+
+    pub fn add_ipv4_addr(&self, addr: Ipv4Addr) -> Result<(), TunTapError> {
+        self.set_ipv4_ioctl(self.if_name, SIOCSIFADDR, "SIOCSIFADDR", addr)
+    }
+
+    /// Adds an IPv4 address and applies the netmask for `prefix_len`.
+    pub fn add_ipv4_addr_with_prefix(&self, addr: Ipv4Addr, prefix_len: u8)
+                                     -> Result<(), TunTapError> {
+        let mask = ipv4_netmask(prefix_len)?;
+        self.add_ipv4_addr(addr)?;
+        self.set_ipv4_netmask(mask)
+    }
+
+    /// Adds a secondary address under the label `<name>:<label_suffix>`,
+    /// like `ip addr add ADDR/PREFIX dev NAME label NAME:SUFFIX`. The label
+    /// has to fit the same length limit as interface names.
+    pub fn add_ipv4_alias(&self, label_suffix: &str, addr: Ipv4Addr, prefix_len: u8)
+                          -> Result<(), TunTapError> {
+        // Same character rules as for names, except for the ':' we add
+        if label_suffix.is_empty() {
+            return Err(TunTapError::InvalidName);
+        }
+        TunTap::ifr_name(label_suffix)?;
+        let label = format!("{}:{}", self.get_name(), label_suffix);
+        if label.len() >= IFNAMSIZ {
+            return Err(TunTapError::NameTooLong { max: IFNAMSIZ - 1 });
+        }
+        let mut if_name = [0u8; IFNAMSIZ];
+        if_name[..label.len()].copy_from_slice(label.as_bytes());
+
+        let mask = ipv4_netmask(prefix_len)?;
+        self.set_ipv4_ioctl(if_name, SIOCSIFADDR, "SIOCSIFADDR", addr)?;
+        self.set_ipv4_ioctl(if_name, SIOCSIFNETMASK, "SIOCSIFNETMASK", mask)
+    }
+
+    pub fn set_ipv4_netmask(&self, mask: Ipv4Addr) -> Result<(), TunTapError> {
+        self.set_ipv4_ioctl(self.if_name, SIOCSIFNETMASK, "SIOCSIFNETMASK", mask)
+    }
 
+    pub fn get_ipv4_netmask(&self) -> Result<Ipv4Addr, TunTapError> {
+        self.get_ipv4_ioctl(SIOCGIFNETMASK, "SIOCGIFNETMASK")
+    }
+
+    /// Sets the address of the remote end of a point-to-point link and marks
+    /// the interface `IFF_POINTOPOINT`. Together with `add_ipv4_addr` this is
+    /// `ip addr add LOCAL peer REMOTE dev NAME`; the local address has to
+    /// be set first.
+    pub fn set_ipv4_peer(&self, addr: Ipv4Addr) -> Result<(), TunTapError> {
+        self.set_ipv4_ioctl(self.if_name, SIOCSIFDSTADDR, "SIOCSIFDSTADDR", addr)?;
+        let flags = self.get_flags()?;
+        self.set_flags(flags | IFF_POINTOPOINT as i32)
+    }
+
+    /// Sets the IPv4 broadcast address and marks the interface
+    /// `IFF_BROADCAST`. Like the peer address, this needs a local address.
+    pub fn set_broadcast(&self, addr: Ipv4Addr) -> Result<(), TunTapError> {
+        self.set_ipv4_ioctl(self.if_name, SIOCSIFBRDADDR, "SIOCSIFBRDADDR", addr)?;
+        let flags = self.get_flags()?;
+        self.set_flags(flags | IFF_BROADCAST as i32)
+    }
+
+    pub fn get_broadcast(&self) -> Result<Ipv4Addr, TunTapError> {
+        self.get_ipv4_ioctl(SIOCGIFBRDADDR, "SIOCGIFBRDADDR")
+    }
+
+    /// Sets the IPv4 address from a `sockaddr_in` that is passed to the
+    /// kernel as is, for code that builds its own socket addresses.
+    pub fn add_ipv4_sockaddr(&self, sa: sockaddr_in) -> Result<(), TunTapError> {
+        self.set_sockaddr_ioctl(self.if_name, SIOCSIFADDR, "SIOCSIFADDR", sa)
+    }
+
+    fn set_ipv4_ioctl(&self, if_name: [u8; IFNAMSIZ], request: c_ulong, call: &'static str,
+                      addr: Ipv4Addr) -> Result<(), TunTapError> {
+        self.set_sockaddr_ioctl(if_name, request, call, ipv4_sockaddr(addr))
+    }
+
+    fn set_sockaddr_ioctl(&self, if_name: [u8; IFNAMSIZ], request: c_ulong, call: &'static str,
+                          sa: sockaddr_in) -> Result<(), TunTapError> {
         let mut req = in_ifreq {
-            ifr_name: self.if_name,
-            ifr_addr: sock_addr,
+            ifr_name: if_name,
+            ifr_addr: sa,
+            ifr_pad: [0; IFREQ_PAD],
         };
 
-        let res = unsafe { ioctl(sock, SIOCSIFADDR, &mut req) };
+        let res = unsafe { ioctl(self.sock.0, request, &mut req) };
         if res < 0 {
-            unsafe { close(sock) };
-            panic!("{}", io::Error::last_os_error());
+            return Err(TunTapError::ioctl(call));
         }
-        unsafe { close(sock) };
+        Ok(())
     }
 
-    pub fn add_ipv6_addr(&self, addr: Ipv6Addr) {
-        let segments = addr.segments();
-        let mut ifr6_addr: in6_addr = unsafe { mem::zeroed() };
-        ifr6_addr.s6_addr = [
-            (segments[0] >> 8) as u8, segments[0] as u8,
-            (segments[1] >> 8) as u8, segments[1] as u8,
-            (segments[2] >> 8) as u8, segments[2] as u8,
-            (segments[3] >> 8) as u8, segments[3] as u8,
-            (segments[4] >> 8) as u8, segments[4] as u8,
-            (segments[5] >> 8) as u8, segments[5] as u8,
-            (segments[6] >> 8) as u8, segments[6] as u8,
-            (segments[7] >> 8) as u8, segments[7] as u8,
-        ];
-        let sock = TunTap::create_socket(AF_INET6);
-        let mut req = ioctl_ifindex_data {
-            ifr_name: self.if_name,
-            ifr_ifindex: -1,
-        };
-        let res = unsafe { ioctl(sock, SIOCGIFINDEX, &mut req) };
+    /// Adds a route to `dest`/`prefix_len` through this interface, like
+    /// `ip route add DEST/PREFIX [via GATEWAY] dev NAME`. Host bits of `dest`
+    /// are cleared, so the interface's own address gives the subnet route.
+    pub fn add_route(&self, dest: IpAddr, prefix_len: u8, gateway: Option<IpAddr>)
+                     -> Result<(), TunTapError> {
+        self.route_ioctl(SIOCADDRT, "SIOCADDRT", dest, prefix_len, gateway)
+    }
+
+    /// Removes a route added with `add_route`.
+    pub fn del_route(&self, dest: IpAddr, prefix_len: u8, gateway: Option<IpAddr>)
+                     -> Result<(), TunTapError> {
+        self.route_ioctl(SIOCDELRT, "SIOCDELRT", dest, prefix_len, gateway)
+    }
+
+    fn route_ioctl(&self, request: c_ulong, call: &'static str, dest: IpAddr, prefix_len: u8,
+                   gateway: Option<IpAddr>) -> Result<(), TunTapError> {
+        match (dest, gateway) {
+            (IpAddr::V4(dest), None) => {
+                self.ipv4_route_ioctl(request, call, dest, prefix_len, None)
+            }
+            (IpAddr::V4(dest), Some(IpAddr::V4(gateway))) => {
+                self.ipv4_route_ioctl(request, call, dest, prefix_len, Some(gateway))
+            }
+            (IpAddr::V6(dest), None) => {
+                self.ipv6_route_ioctl(request, call, dest, prefix_len, None)
+            }
+            (IpAddr::V6(dest), Some(IpAddr::V6(gateway))) => {
+                self.ipv6_route_ioctl(request, call, dest, prefix_len, Some(gateway))
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                    "Route gateway is from a different address family").into()),
+        }
+    }
+
+    fn ipv4_route_ioctl(&self, request: c_ulong, call: &'static str, dest: Ipv4Addr,
+                        prefix_len: u8, gateway: Option<Ipv4Addr>) -> Result<(), TunTapError> {
+        let mask = ipv4_netmask(prefix_len)?;
+        let dest = Ipv4Addr::from(u32::from(dest) & u32::from(mask));
+        let mut if_name = self.if_name;
+        let mut req: rtentry = unsafe { mem::zeroed() };
+        req.rt_dst = ipv4_sockaddr(dest);
+        req.rt_genmask = ipv4_sockaddr(mask);
+        req.rt_flags = RTF_UP;
+        if prefix_len == 32 {
+            req.rt_flags |= RTF_HOST;
+        }
+        if let Some(gateway) = gateway {
+            req.rt_gateway = ipv4_sockaddr(gateway);
+            req.rt_flags |= RTF_GATEWAY;
+        }
+        req.rt_dev = if_name.as_mut_ptr() as *mut c_char;
+
+        let res = unsafe { ioctl(self.sock.0, request, &mut req) };
         if res < 0 {
-            unsafe { close(sock) };
-            let err = io::Error::last_os_error();
-            panic!("{}", err);
+            return Err(TunTapError::ioctl(call));
         }
-        let mut req = in6_ifreq {
-            ifr6_addr: ifr6_addr,
-            ifr6_prefixlen: 8,
-            ifr6_ifindex: req.ifr_ifindex,
-        };
-        let res = unsafe { ioctl(sock, SIOCSIFADDR, &mut req) };
+        Ok(())
+    }
+
+    fn ipv6_route_ioctl(&self, request: c_ulong, call: &'static str, dest: Ipv6Addr,
+                        prefix_len: u8, gateway: Option<Ipv6Addr>) -> Result<(), TunTapError> {
+        if prefix_len > 128 {
+            return Err(TunTapError::InvalidPrefixLength { len: prefix_len, max: 128 });
+        }
+        let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+        let dest = Ipv6Addr::from(u128::from(dest) & mask);
+        let mut req: in6_rtmsg = unsafe { mem::zeroed() };
+        req.rtmsg_dst = ipv6_in6_addr(dest);
+        req.rtmsg_dst_len = prefix_len as u16;
+        req.rtmsg_flags = RTF_UP as u32;
+        if let Some(gateway) = gateway {
+            req.rtmsg_gateway = ipv6_in6_addr(gateway);
+            req.rtmsg_flags |= RTF_GATEWAY as u32;
+        }
+        req.rtmsg_ifindex = self.get_index()?;
+
+        let res = unsafe { ioctl(self.inet6_socket()?, request, &mut req) };
         if res < 0 {
-            unsafe { close(sock) };
-            panic!("{}", io::Error::last_os_error());
+            return Err(TunTapError::ioctl(call));
         }
-        unsafe { close(sock) };
+        Ok(())
     }
 
-    pub fn set_mac(&self, mac: [u8; 6]) {
-        let sock = TunTap::create_socket(AF_INET);
-        let mut req = ioctl_mac {
+    /// Returns the IPv4 address of the interface, or an error if it has none.
+    pub fn get_ipv4_addr(&self) -> Result<Ipv4Addr, TunTapError> {
+        self.get_ipv4_ioctl(SIOCGIFADDR, "SIOCGIFADDR")
+    }
+
+    fn get_ipv4_ioctl(&self, request: c_ulong, call: &'static str)
+                      -> Result<Ipv4Addr, TunTapError> {
+        let mut req = in_ifreq {
             ifr_name: self.if_name,
-            ifr_addr: sockaddr {
-                sa_family: 0x01 as sa_family_t,
-                sa_data: [0; 14],
-            },
+            ifr_addr: ipv4_sockaddr(Ipv4Addr::UNSPECIFIED),
+            ifr_pad: [0; IFREQ_PAD],
         };
-        for (i, b) in mac.iter().enumerate() {
-            req.ifr_addr.sa_data[i] = *b as c_char;
-        }
-        let res = unsafe { ioctl(sock, SIOCSIFHWADDR, &req) };
+
+        let res = unsafe { ioctl(self.sock.0, request, &mut req) };
         if res < 0 {
-            unsafe { close(sock) };
-            panic!("{}", io::Error::last_os_error());
+            return Err(TunTapError::ioctl(call));
         }
-        unsafe { close(sock) };
+        Ok(sockaddr_ipv4(&req.ifr_addr))
     }
 
-    pub fn add_address(&self, addr: IpAddr) {
-        match addr {
-            IpAddr::V4(value) => self.add_ipv4_addr(value),
-            IpAddr::V6(value) => self.add_ipv6_addr(value),
+    /// Returns the kernel's interface index, as used by netlink and routing.
+    pub fn get_index(&self) -> Result<i32, TunTapError> {
+        let mut req = ioctl_ifindex_data {
+            ifr_name: self.if_name,
+            ifr_ifindex: -1,
+            ifr_pad: [0; IFREQ_PAD],
+        };
+        let res = unsafe { ioctl(self.sock.0, SIOCGIFINDEX, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("SIOCGIFINDEX"));
         }
+        Ok(req.ifr_ifindex)
     }
 
-    pub fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
-        assert!(buffer.len() >= MTU_SIZE);
+    /// Adds an IPv6 address with a /64 prefix.
+    pub fn add_ipv6_addr(&self, addr: Ipv6Addr) -> Result<(), TunTapError> {
+        self.add_ipv6_addr_with_prefix(addr, 64)
+    }
 
-        let len = try!(self.file.read(buffer));
-        Ok(len)
+    pub fn add_ipv6_addr_with_prefix(&self, addr: Ipv6Addr, prefix_len: u8)
+                                     -> Result<(), TunTapError> {
+        self.ipv6_ioctl(SIOCSIFADDR, "SIOCSIFADDR", addr, prefix_len)
     }
 
-    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
-        self.file.write_all(data)
+    /// Removes an IPv6 address previously added with the same prefix length.
+    pub fn remove_ipv6_addr(&self, addr: Ipv6Addr, prefix_len: u8) -> Result<(), TunTapError> {
+        self.ipv6_ioctl(SIOCDIFADDR, "SIOCDIFADDR", addr, prefix_len)
+    }
+
+    fn ipv6_ioctl(&self, request: c_ulong, call: &'static str, addr: Ipv6Addr, prefix_len: u8)
+                  -> Result<(), TunTapError> {
+        if prefix_len > 128 {
+            return Err(TunTapError::InvalidPrefixLength { len: prefix_len, max: 128 });
+        }
+
+        let sock = self.inet6_socket()?;
+        let mut req = in6_ifreq {
+            ifr6_addr: ipv6_in6_addr(addr),
+            ifr6_prefixlen: prefix_len as u32,
+            ifr6_ifindex: self.get_index()?,
+        };
+        let res = unsafe { ioctl(sock, request, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl(call));
+        }
+        Ok(())
+    }
+
+    /// Returns the IPv6 addresses of the interface with their prefix
+    /// lengths, including the link-local one the kernel assigns on bring-up
+    /// (see `Ipv6Addr::is_unicast_link_local`). Empty on hosts with IPv6
+    /// disabled.
+    pub fn get_ipv6_addrs(&self) -> Result<Vec<(Ipv6Addr, u8)>, TunTapError> {
+        // Each line is "<addr> <ifindex> <prefix len> <scope> <flags> <name>"
+        // with everything but the name in hex
+        let mut contents = String::new();
+        match File::open(IF_INET6_PATH) {
+            Ok(mut file) => file.read_to_string(&mut contents)?,
+            // Only there with IPv6 enabled
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let name = self.get_name();
+        let mut addrs = Vec::new();
+        for line in contents.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 || fields[5] != name || fields[0].len() != 32 {
+                continue;
+            }
+            let addr = match u128::from_str_radix(fields[0], 16) {
+                Ok(addr) => Ipv6Addr::from(addr),
+                Err(_) => continue,
+            };
+            let prefix_len = match u8::from_str_radix(fields[2], 16) {
+                Ok(prefix_len) => prefix_len,
+                Err(_) => continue,
+            };
+            addrs.push((addr, prefix_len));
+        }
+        Ok(addrs)
+    }
+
+    /// Returns the first IPv6 address of the interface that isn't link-local,
+    /// with its prefix length, e.g. to pick a source address for traffic
+    /// routed through the tunnel.
+    pub fn get_ipv6_addr(&self) -> Result<Option<(Ipv6Addr, u8)>, TunTapError> {
+        Ok(self.get_ipv6_addrs()?.into_iter().find(|&(addr, _)| !addr.is_unicast_link_local()))
+    }
+
+    /// Returns the fe80::/10 address the kernel assigned the interface on
+    /// bring-up, with its prefix length.
+    pub fn get_ipv6_link_local(&self) -> Result<Option<(Ipv6Addr, u8)>, TunTapError> {
+        Ok(self.get_ipv6_addrs()?.into_iter().find(|&(addr, _)| addr.is_unicast_link_local()))
+    }
+
+    /// Clears the IPv4 address of the interface.
+    pub fn remove_ipv4_addr(&self) -> Result<(), TunTapError> {
+        self.add_ipv4_addr(Ipv4Addr::new(0, 0, 0, 0))
+    }
+
+    /// Removes the IPv4 address and every IPv6 address, including the
+    /// link-local one, from the interface.
+    pub fn flush_addresses(&self) -> Result<(), TunTapError> {
+        self.remove_ipv4_addr()?;
+        for (addr, prefix_len) in self.get_ipv6_addrs()? {
+            self.remove_ipv6_addr(addr, prefix_len)?;
+        }
+        Ok(())
+    }
+
+    /// Adds this TAP device to the existing Linux bridge `bridge`, like
+    /// `ip link set <name> master <bridge>`.
+    pub fn add_to_bridge(&self, bridge: &str) -> Result<(), TunTapError> {
+        self.bridge_ioctl(bridge, SIOCBRADDIF, "SIOCBRADDIF")
+    }
+
+    /// Removes this TAP device from `bridge`.
+    pub fn remove_from_bridge(&self, bridge: &str) -> Result<(), TunTapError> {
+        self.bridge_ioctl(bridge, SIOCBRDELIF, "SIOCBRDELIF")
+    }
+
+    // Bridge ports are set on the bridge, identified by name, with the
+    // index of the port
+    fn bridge_ioctl(&self, bridge: &str, request: c_ulong, call: &'static str)
+                    -> Result<(), TunTapError> {
+        if self.get_type() != TunTapType::Tap {
+            return Err(TunTapError::RequiresTap);
+        }
+        if bridge.is_empty() {
+            return Err(TunTapError::InvalidName);
+        }
+
+        let mut req = ioctl_ifindex_data {
+            ifr_name: TunTap::ifr_name(bridge)?,
+            ifr_ifindex: self.get_index()?,
+            ifr_pad: [0; IFREQ_PAD],
+        };
+        let res = unsafe { ioctl(self.sock.0, request, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl(call));
+        }
+        Ok(())
+    }
+
+    /// Installs a classic BPF program that the kernel runs on every frame
+    /// before queueing it, dropping those it returns 0 for. Only TAP devices
+    /// support filters.
+    pub fn attach_filter(&self, prog: &[SockFilter]) -> Result<(), TunTapError> {
+        if self.get_type() != TunTapType::Tap {
+            return Err(TunTapError::RequiresTap);
+        }
+        if prog.len() > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "BPF program too long").into());
+        }
+
+        let mut fprog = sock_fprog {
+            len: prog.len() as u16,
+            filter: prog.as_ptr() as *mut sock_filter,
+        };
+        let res = unsafe { ioctl(self.file.as_raw_fd(), TUNATTACHFILTER, &mut fprog) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("TUNATTACHFILTER"));
+        }
+        Ok(())
+    }
+
+    /// Removes the program installed with `attach_filter`.
+    pub fn detach_filter(&self) -> Result<(), TunTapError> {
+        if self.get_type() != TunTapType::Tap {
+            return Err(TunTapError::RequiresTap);
+        }
+
+        let mut fprog: sock_fprog = unsafe { mem::zeroed() };
+        let res = unsafe { ioctl(self.file.as_raw_fd(), TUNDETACHFILTER, &mut fprog) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("TUNDETACHFILTER"));
+        }
+        Ok(())
+    }
+
+    /// Reads the interface counters from `/sys/class/net/<name>/statistics`.
+    pub fn get_statistics(&self) -> Result<InterfaceStats, TunTapError> {
+        InterfaceStats::from_dir(self.sysfs_path("statistics"))
+    }
+
+    /// Reads the link speed, duplex and carrier from `/sys/class/net/<name>`.
+    pub fn get_link_info(&self) -> Result<LinkInfo, TunTapError> {
+        LinkInfo::from_dir(self.sysfs_path(""))
+    }
+
+    /// Sets the interface description shown by `ip link`, at most 255
+    /// bytes. An empty alias clears it.
+    pub fn set_alias(&self, alias: &str) -> Result<(), TunTapError> {
+        if alias.len() > 255 || alias.contains('\n') {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid interface alias").into());
+        }
+        // The kernel strips the newline; without it an empty alias would be
+        // a zero-length write, which sysfs never passes on
+        fs::write(self.sysfs_path("ifalias"), format!("{}\n", alias))?;
+        Ok(())
+    }
+
+    pub fn get_alias(&self) -> Result<String, TunTapError> {
+        let alias = fs::read_to_string(self.sysfs_path("ifalias"))?;
+        Ok(alias.trim_end_matches('\n').to_string())
+    }
+
+    fn sysfs_path(&self, entry: &str) -> PathBuf {
+        Path::new(SYS_CLASS_NET).join(self.get_name()).join(entry)
+    }
+
+    pub fn set_mac(&self, mac: [u8; 6]) -> Result<(), TunTapError> {
+        if self.get_type() != TunTapType::Tap {
+            return Err(TunTapError::RequiresTap);
+        }
+
+        let sock = self.sock.0;
+        let mut req = ioctl_mac {
+            ifr_name: self.if_name,
+            ifr_addr: sockaddr {
+                sa_family: 0x01 as sa_family_t,
+                sa_data: [0; 14],
+            },
+            ifr_pad: [0; IFREQ_PAD],
+        };
+        for (i, b) in mac.iter().enumerate() {
+            req.ifr_addr.sa_data[i] = *b as c_char;
+        }
+        let res = unsafe { ioctl(sock, SIOCSIFHWADDR, &req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("SIOCSIFHWADDR"));
+        }
+        Ok(())
+    }
+
+    /// Returns the hardware address of the interface. Only TAP devices have
+    /// one, so this fails with `TunTapError::RequiresTap` for TUN devices.
+    pub fn get_mac(&self) -> Result<[u8; 6], TunTapError> {
+        if self.get_type() != TunTapType::Tap {
+            return Err(TunTapError::RequiresTap);
+        }
+
+        let mut req = ioctl_mac {
+            ifr_name: self.if_name,
+            ifr_addr: sockaddr {
+                sa_family: 0,
+                sa_data: [0; 14],
+            },
+            ifr_pad: [0; IFREQ_PAD],
+        };
+        let res = unsafe { ioctl(self.sock.0, SIOCGIFHWADDR, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("SIOCGIFHWADDR"));
+        }
+
+        let mut mac = [0u8; 6];
+        for (i, b) in mac.iter_mut().enumerate() {
+            *b = req.ifr_addr.sa_data[i] as u8;
+        }
+        Ok(mac)
+    }
+
+    /// Marks the device as persistent, so it outlives this process.
+    ///
+    /// Devices are non-persistent by default and disappear once the last file
+    /// descriptor is closed. Changing persistence requires CAP_NET_ADMIN.
+    pub fn set_persistent(&self, persist: bool) -> Result<(), TunTapError> {
+        let res = unsafe { ioctl(self.file.as_raw_fd(), TUNSETPERSIST, persist as c_ulong) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("TUNSETPERSIST"));
+        }
+        Ok(())
+    }
+
+    /// Allows the user `uid` to attach to this device without CAP_NET_ADMIN.
+    pub fn set_owner(&self, uid: uid_t) -> Result<(), TunTapError> {
+        let res = unsafe { ioctl(self.file.as_raw_fd(), TUNSETOWNER, uid as c_ulong) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("TUNSETOWNER"));
+        }
+        Ok(())
+    }
+
+    /// Allows members of the group `gid` to attach to this device without CAP_NET_ADMIN.
+    pub fn set_group(&self, gid: gid_t) -> Result<(), TunTapError> {
+        let res = unsafe { ioctl(self.file.as_raw_fd(), TUNSETGROUP, gid as c_ulong) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("TUNSETGROUP"));
+        }
+        Ok(())
+    }
+
+    /// Switches the device into non-blocking mode, where read() fails with
+    /// `ErrorKind::WouldBlock` instead of waiting for a packet.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), TunTapError> {
+        let fd = self.file.as_raw_fd();
+        let flags = unsafe { fcntl(fd, F_GETFL) };
+        if flags < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let flags = if nonblocking {
+            flags | O_NONBLOCK
+        } else {
+            flags & !O_NONBLOCK
+        };
+        let res = unsafe { fcntl(fd, F_SETFL, flags) };
+        if res < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    /// Controls whether the device is closed when the process execs. It is
+    /// by default, so children don't inherit the device by accident.
+    pub fn set_cloexec(&self, cloexec: bool) -> Result<(), TunTapError> {
+        Ok(socket::set_cloexec(self.file.as_raw_fd(), cloexec)?)
+    }
+
+    /// Tells the kernel which offloads userspace can handle.
+    ///
+    /// Offloaded packets carry their checksum and segmentation state in the
+    /// virtio-net header, so this only makes sense for devices using one.
+    /// With TSO or UFO enabled, a single read can return a packet much
+    /// larger than the MTU.
+    pub fn set_offload(&self, features: OffloadFlags) -> Result<(), TunTapError> {
+        let res = unsafe {
+            ioctl(self.file.as_raw_fd(), TUNSETOFFLOAD, features.bits() as c_ulong)
+        };
+        if res < 0 {
+            return Err(TunTapError::ioctl("TUNSETOFFLOAD"));
+        }
+        self.offload.store(features.bits(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Sets the size of the virtio-net header, either `VnetHdr::SIZE` or 12
+    /// for the variant with `num_buffers`.
+    pub fn set_vnet_hdr_size(&self, size: usize) -> Result<(), TunTapError> {
+        if size != VnetHdr::SIZE && size != VnetHdr::SIZE + 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Unsupported virtio-net header size").into());
+        }
+
+        let size_c = size as c_int;
+        let res = unsafe { ioctl(self.file.as_raw_fd(), TUNSETVNETHDRSZ, &size_c) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("TUNSETVNETHDRSZ"));
+        }
+        self.vnet_hdr_len.store(size, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns the size of the virtio-net header. Fails with `InvalidInput`
+    /// for sizes other than the two `set_vnet_hdr_size` accepts, which
+    /// another process may have set on a shared descriptor.
+    pub fn get_vnet_hdr_size(&self) -> Result<usize, TunTapError> {
+        let mut size: c_int = 0;
+        let res = unsafe { ioctl(self.file.as_raw_fd(), TUNGETVNETHDRSZ, &mut size) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("TUNGETVNETHDRSZ"));
+        }
+        let size = size as usize;
+        if size != VnetHdr::SIZE && size != VnetHdr::SIZE + 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Unsupported virtio-net header size").into());
+        }
+        self.vnet_hdr_len.store(size, Ordering::Relaxed);
+        Ok(size)
+    }
+
+    /// Sets how many bytes of packets written to the device may be queued
+    /// before writes block (or fail with `WouldBlock`). Larger values absorb
+    /// bursts, smaller ones bound the queueing latency.
+    pub fn set_sndbuf(&self, bytes: u32) -> Result<(), TunTapError> {
+        if bytes == 0 || bytes > c_int::MAX as u32 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Send buffer size out of range").into());
+        }
+
+        let bytes_c = bytes as c_int;
+        let res = unsafe { ioctl(self.file.as_raw_fd(), TUNSETSNDBUF, &bytes_c) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("TUNSETSNDBUF"));
+        }
+        Ok(())
+    }
+
+    pub fn get_sndbuf(&self) -> Result<u32, TunTapError> {
+        let mut bytes: c_int = 0;
+        let res = unsafe { ioctl(self.file.as_raw_fd(), TUNGETSNDBUF, &mut bytes) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("TUNGETSNDBUF"));
+        }
+        Ok(bytes as u32)
+    }
+
+    /// Sets the interface MTU.
+    pub fn set_mtu(&self, mtu: u32) -> Result<(), TunTapError> {
+        let sock = self.sock.0;
+        let mut req = ioctl_mtu_data {
+            ifr_name: self.if_name,
+            ifr_mtu: mtu as c_int,
+            ifr_pad: [0; IFREQ_PAD],
+        };
+        let res = unsafe { ioctl(sock, SIOCSIFMTU, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("SIOCSIFMTU"));
+        }
+        self.mtu.store(mtu as usize, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Queries the interface MTU from the kernel.
+    pub fn get_mtu(&self) -> Result<u32, TunTapError> {
+        let sock = self.sock.0;
+        let mut req = ioctl_mtu_data {
+            ifr_name: self.if_name,
+            ifr_mtu: 0,
+            ifr_pad: [0; IFREQ_PAD],
+        };
+        let res = unsafe { ioctl(sock, SIOCGIFMTU, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("SIOCGIFMTU"));
+        }
+        self.mtu.store(req.ifr_mtu as usize, Ordering::Relaxed);
+        Ok(req.ifr_mtu as u32)
+    }
+
+    /// Sets the length of the transmit queue, i.e. how many packets the
+    /// kernel buffers for this device before dropping.
+    pub fn set_txqueuelen(&self, len: u32) -> Result<(), TunTapError> {
+        let mut req = ioctl_qlen_data {
+            ifr_name: self.if_name,
+            ifr_qlen: len as c_int,
+            ifr_pad: [0; IFREQ_PAD],
+        };
+        let res = unsafe { ioctl(self.sock.0, SIOCSIFTXQLEN, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("SIOCSIFTXQLEN"));
+        }
+        Ok(())
+    }
+
+    pub fn get_txqueuelen(&self) -> Result<u32, TunTapError> {
+        let mut req = ioctl_qlen_data {
+            ifr_name: self.if_name,
+            ifr_qlen: 0,
+            ifr_pad: [0; IFREQ_PAD],
+        };
+        let res = unsafe { ioctl(self.sock.0, SIOCGIFTXQLEN, &mut req) };
+        if res < 0 {
+            return Err(TunTapError::ioctl("SIOCGIFTXQLEN"));
+        }
+        Ok(req.ifr_qlen as u32)
+    }
+
+    pub fn add_address(&self, addr: IpAddr) -> Result<(), TunTapError> {
+        match addr {
+            IpAddr::V4(value) => self.add_ipv4_addr(value),
+            IpAddr::V6(value) => self.add_ipv6_addr(value),
+        }
+    }
+
+    pub fn add_address_with_prefix(&self, addr: IpAddr, prefix_len: u8)
+                                   -> Result<(), TunTapError> {
+        match addr {
+            IpAddr::V4(value) => self.add_ipv4_addr_with_prefix(value, prefix_len),
+            IpAddr::V6(value) => self.add_ipv6_addr_with_prefix(value, prefix_len),
+        }
+    }
+
+    /// Adds all of `addrs` with their prefix lengths, or none of them: if
+    /// one fails, the addresses added before it are removed again and the
+    /// IPv4 address it replaced is restored. An interface has a single
+    /// IPv4 address, so `addrs` can hold at most one, see `add_ipv4_alias`.
+    pub fn set_addresses(&self, addrs: &[(IpAddr, u8)]) -> Result<(), TunTapError> {
+        let mut has_ipv4 = false;
+        for &(addr, prefix_len) in addrs {
+            let max = if addr.is_ipv4() { 32 } else { 128 };
+            if prefix_len > max {
+                return Err(TunTapError::InvalidPrefixLength { len: prefix_len, max });
+            }
+            if addr.is_ipv4() {
+                if has_ipv4 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                              "More than one IPv4 address").into());
+                }
+                has_ipv4 = true;
+            }
+        }
+
+        let previous_ipv4 = match self.get_ipv4_addr() {
+            Ok(addr) if has_ipv4 => Some((addr, self.get_ipv4_netmask()?)),
+            _ => None,
+        };
+        for (index, &(addr, prefix_len)) in addrs.iter().enumerate() {
+            if let Err(err) = self.add_address_with_prefix(addr, prefix_len) {
+                // The IPv4 address may have been replaced before setting the
+                // netmask failed, but an IPv6 address that failed was never
+                // added, or was there before
+                let added = if addr.is_ipv4() { &addrs[..=index] } else { &addrs[..index] };
+                self.remove_added_addresses(added, previous_ipv4);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    // Best effort, the error that made set_addresses give up is the one
+    // reported
+    fn remove_added_addresses(&self, added: &[(IpAddr, u8)],
+                              previous_ipv4: Option<(Ipv4Addr, Ipv4Addr)>) {
+        for &(addr, prefix_len) in added.iter().rev() {
+            let _ = match (addr, previous_ipv4) {
+                (IpAddr::V4(_), Some((addr, mask))) => {
+                    self.add_ipv4_addr(addr).and_then(|()| self.set_ipv4_netmask(mask))
+                }
+                (IpAddr::V4(_), None) => self.remove_ipv4_addr(),
+                (IpAddr::V6(addr), _) => self.remove_ipv6_addr(addr, prefix_len),
+            };
+        }
+    }
+
+    /// Reads a single packet into `buffer`.
+    ///
+    /// Buffers smaller than the interface MTU are allowed, but a packet that
+    /// doesn't fit is truncated by the kernel and the rest of it is lost,
+    /// use `read_exact_frame` to find out when that happens.
+    /// Reads interrupted by a signal are retried.
+    pub fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        retry_eintr(|| self.read_interruptible(buffer))
+    }
+
+    /// Like `read`, but a packet that doesn't fit into `buffer` fails with
+    /// `TunTapError::FrameTruncated` (as an `InvalidData` I/O error) instead
+    /// of being returned cut short. The truncated packet is consumed.
+    pub fn read_exact_frame(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        // The kernel caps the returned length at the buffer size, so a
+        // spare byte past the buffer tells a full fit from a truncation
+        let capacity = buffer.len();
+        let mut spare = [0u8; 1];
+        loop {
+            self.wait_for_read()?;
+            let bufs = &mut [IoSliceMut::new(buffer), IoSliceMut::new(&mut spare)];
+            match self.read_fd_vectored(bufs) {
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Ok(len) if len > capacity => {
+                    return Err(TunTapError::FrameTruncated { len: capacity }.into());
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Like `read`, but fails with `Interrupted` when a signal arrives
+    /// before a packet, for callers that want to react to the signal.
+    pub fn read_interruptible(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.wait_for_read()?;
+        self.read_fd(buffer)
+    }
+
+    /// Like `read`, but into a buffer that doesn't need to be initialized
+    /// first, saving a memset per packet.
+    ///
+    /// On success the first `n` bytes of `buffer` are initialized, where `n`
+    /// is the returned length; the rest are left untouched.
+    pub fn read_uninit(&mut self, buffer: &mut [MaybeUninit<u8>]) -> io::Result<usize> {
+        let len = retry_eintr(|| {
+            self.wait_for_read()?;
+            let res = unsafe {
+                read(self.file.as_raw_fd(), buffer.as_mut_ptr() as *mut c_void, buffer.len())
+            };
+            if res < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(res as usize)
+        })?;
+        self.touch();
+        Ok(len)
+    }
+
+    /// Returns a buffer size that fits any single read from this device:
+    /// the MTU, plus the Ethernet header on TAP devices and any packet
+    /// information or virtio-net header. With segmentation offloads enabled
+    /// packets can grow to 64 KiB regardless of the MTU.
+    pub fn recommended_buffer_size(&self) -> usize {
+        let mut size = self.mtu.load(Ordering::Relaxed);
+        let segmentation = (OffloadFlags::TSO4 | OffloadFlags::TSO6 | OffloadFlags::UFO).bits();
+        if self.if_flags & IFF_VNET_HDR != 0 &&
+           self.offload.load(Ordering::Relaxed) & segmentation != 0 {
+            size = size.max(u16::MAX as usize);
+        }
+        if self.get_type() == TunTapType::Tap {
+            // Ethernet header plus a VLAN tag
+            size += 18;
+        }
+        if self.has_packet_info() {
+            size += PacketInfo::SIZE;
+        }
+        if self.if_flags & IFF_VNET_HDR != 0 {
+            size += self.vnet_hdr_len.load(Ordering::Relaxed);
+        }
+        size
+    }
+
+    /// Reads a single packet into a newly allocated buffer of
+    /// `recommended_buffer_size` bytes.
+    pub fn read_packet(&mut self) -> io::Result<Vec<u8>> {
+        let mut buffer = vec![0u8; self.recommended_buffer_size()];
+        let len = self.read(&mut buffer)?;
+        buffer.truncate(len);
+        Ok(buffer)
+    }
+
+    /// Reads packets into `bufs` until the device has no more queued,
+    /// storing each packet's length in `lens`. Returns the number of packets
+    /// read, at most the length of the shorter slice.
+    ///
+    /// Only the first read waits for a packet (or fails with `WouldBlock` in
+    /// non-blocking mode); the batch ends as soon as nothing else is queued.
+    pub fn read_batch<B: AsMut<[u8]>>(&mut self, bufs: &mut [B], lens: &mut [usize])
+                                      -> io::Result<usize> {
+        let max = bufs.len().min(lens.len());
+        if max == 0 {
+            return Ok(0);
+        }
+
+        lens[0] = self.read(bufs[0].as_mut())?;
+        let mut count = 1;
+        while count < max {
+            match self.read_nowait(bufs[count].as_mut()) {
+                Ok(len) => lens[count] = len,
+                // Nothing left, or another reader of the same queue got there first
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Like `read_batch`, but fills the slots of a reusable `PacketBatch`.
+    /// Packets from any previous read are cleared first. Returns the number
+    /// of packets read, also available as `batch.len()`.
+    pub fn read_into_batch(&mut self, batch: &mut PacketBatch) -> io::Result<usize> {
+        batch.clear();
+        if batch.capacity() == 0 {
+            return Ok(0);
+        }
+
+        let len = self.read(batch.next_slot())?;
+        batch.push(len);
+        while !batch.is_full() {
+            match self.read_nowait(batch.next_slot()) {
+                Ok(len) => batch.push(len),
+                // Nothing left, or another reader of the same queue got there first
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(batch.len())
+    }
+
+    /// Reads and discards the packets queued on the device, e.g. so none
+    /// received before a reconfiguration get processed after it. Returns
+    /// how many were dropped. Never blocks, whatever `set_nonblocking` and
+    /// the read timeout say, even with other handles reading the same queue.
+    pub fn drain(&mut self) -> io::Result<usize> {
+        self.check_readable()?;
+        let mut buffer = vec![0u8; self.recommended_buffer_size()];
+        let mut count = 0;
+        loop {
+            match self.read_nowait(&mut buffer) {
+                Ok(_) => count += 1,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(count)
+    }
+
+    // Reads a packet if one is queued and fails with WouldBlock otherwise.
+    // Unlike poll() followed by read() this can't block when another handle
+    // of the queue takes the packet in between, and RWF_NOWAIT leaves the
+    // O_NONBLOCK flag that clones share alone.
+    fn read_nowait(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        let iov = iovec { iov_base: buffer.as_mut_ptr() as *mut c_void, iov_len: buffer.len() };
+        // Offset -1 reads from the current position, like readv()
+        let res = unsafe { preadv2(self.file.as_raw_fd(), &iov, 1, -1, RWF_NOWAIT) };
+        if res < 0 {
+            let err = io::Error::last_os_error();
+            return match err.raw_os_error() {
+                // Older kernels lack RWF_NOWAIT, or don't support it on tun
+                Some(EOPNOTSUPP) | Some(EINVAL) | Some(ENOSYS) => self.read_o_nonblock(buffer),
+                _ => Err(err),
+            };
+        }
+        self.touch();
+        Ok(res as usize)
+    }
+
+    // The fallback for read_nowait, which clones see as briefly
+    // non-blocking
+    fn read_o_nonblock(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        let fd = self.file.as_raw_fd();
+        let flags = unsafe { fcntl(fd, F_GETFL) };
+        if flags < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if flags & O_NONBLOCK != 0 {
+            return self.read_fd(buffer);
+        }
+        if unsafe { fcntl(fd, F_SETFL, flags | O_NONBLOCK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let res = self.read_fd(buffer);
+        unsafe { fcntl(fd, F_SETFL, flags) };
+        res
+    }
+
+    /// Writes `data` as one packet. Like `read`, this retries writes
+    /// interrupted by a signal.
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.check_writable()?;
+        let len = retry_eintr(|| self.write_fd(data))?;
+        if len != data.len() {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "Short write"));
+        }
+        Ok(())
+    }
+
+    /// Writes `data` as one packet with a single write() and returns the
+    /// byte count the kernel reported.
+    ///
+    /// The kernel takes a packet whole or fails the write, e.g. with
+    /// `WouldBlock` once the send buffer (see `set_sndbuf`) is full, so a
+    /// count short of `data.len()` means the packet did not go out as sent.
+    pub fn write_packet(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.check_writable()?;
+        retry_eintr(|| self.write_fd(data))
+    }
+
+    /// Reads a single packet into `buffer` and returns its packet information
+    /// header separately. Only valid for devices created with packet info.
+    pub fn read_with_pi(&mut self, buffer: &mut [u8]) -> io::Result<(PacketInfo, usize)> {
+        self.check_packet_info()?;
+
+        let mut header = [0u8; PacketInfo::SIZE];
+        let len = retry_eintr(|| {
+            self.wait_for_read()?;
+            self.read_fd_vectored(&mut [IoSliceMut::new(&mut header), IoSliceMut::new(buffer)])
+        })?;
+        if len < PacketInfo::SIZE {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      "Packet shorter than the packet information header"));
+        }
+        Ok((PacketInfo::from_bytes(header), len - PacketInfo::SIZE))
+    }
+
+    /// Writes `data` as a single packet, prepending `pi` as its packet
+    /// information header. Only valid for devices created with packet info.
+    pub fn write_with_pi(&mut self, pi: PacketInfo, data: &[u8]) -> io::Result<()> {
+        self.check_writable()?;
+        self.check_packet_info()?;
+
+        let header = pi.to_bytes();
+        let len = retry_eintr(|| {
+            self.write_fd_vectored(&[IoSlice::new(&header), IoSlice::new(data)])
+        })?;
+        if len != header.len() + data.len() {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "Short write"));
+        }
+        Ok(())
+    }
+
+    /// Reads a single packet from a TUN device into `buffer` and tells its
+    /// IP version. Fails with `InvalidData` for packets that are neither
+    /// IPv4 nor IPv6. Devices with packet info or a virtio-net header are
+    /// rejected; use `read_with_pi` or `read_with_vnet_hdr` for those.
+    pub fn read_ip_packet(&mut self, buffer: &mut [u8]) -> io::Result<(IpVersion, usize)> {
+        if self.get_type() != TunTapType::Tun ||
+           self.if_flags & (IFF_NO_PI | IFF_VNET_HDR) != IFF_NO_PI {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Device doesn't deliver bare IP packets"));
+        }
+
+        let len = self.read(buffer)?;
+        match detect_ip_version(&buffer[..len]) {
+            Some(version) => Ok((version, len)),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "Not an IP packet")),
+        }
+    }
+
+    /// Writes an IPv4 or IPv6 packet to a TUN device. On devices with packet
+    /// info a header is prepended whose `proto` is ETH_P_IP or ETH_P_IPV6,
+    /// picked from the version nibble of the packet's first byte, so both
+    /// versions can share a device.
+    pub fn write_ip_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        if self.get_type() != TunTapType::Tun || self.if_flags & IFF_VNET_HDR != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Device doesn't take bare IP packets"));
+        }
+        let version = detect_ip_version(packet).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Not an IPv4 or IPv6 packet")
+        })?;
+
+        if self.has_packet_info() {
+            self.write_with_pi(PacketInfo { flags: 0, proto: version.ethertype() }, packet)
+        } else {
+            self.write(packet)
+        }
+    }
+
+    /// Reads a single frame from a TAP device into `buffer` and parses its
+    /// Ethernet header. The returned frame's payload borrows from `buffer`.
+    pub fn read_frame<'a>(&mut self, buffer: &'a mut [u8]) -> io::Result<EthernetFrame<'a>> {
+        if self.get_type() != TunTapType::Tap {
+            return Err(TunTapError::RequiresTap.into());
+        }
+
+        let len = self.read(buffer)?;
+        EthernetFrame::parse(&buffer[..len]).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "Frame shorter than an Ethernet header")
+        })
+    }
+
+    /// Writes `frame` to a TAP device as a single packet.
+    pub fn write_frame(&mut self, frame: &EthernetFrame) -> io::Result<()> {
+        self.check_writable()?;
+        if self.get_type() != TunTapType::Tap {
+            return Err(TunTapError::RequiresTap.into());
+        }
+
+        let header = frame.header_bytes();
+        let len = retry_eintr(|| {
+            self.write_fd_vectored(&[IoSlice::new(&header), IoSlice::new(frame.payload)])
+        })?;
+        if len != header.len() + frame.payload.len() {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "Short write"));
+        }
+        Ok(())
+    }
+
+    /// Reads a single packet into `buffer` and returns its virtio-net header
+    /// separately. Only valid for devices created with a virtio-net header
+    /// and without packet info.
+    pub fn read_with_vnet_hdr(&mut self, buffer: &mut [u8]) -> io::Result<(VnetHdr, usize)> {
+        let hdr_len = self.check_vnet_hdr()?;
+
+        let mut header = [0u8; VnetHdr::SIZE + 2];
+        let len = retry_eintr(|| {
+            self.wait_for_read()?;
+            self.read_fd_vectored(&mut [IoSliceMut::new(&mut header[..hdr_len]),
+                                        IoSliceMut::new(buffer)])
+        })?;
+        if len < hdr_len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      "Packet shorter than the virtio-net header"));
+        }
+        Ok((VnetHdr::parse(&header).unwrap(), len - hdr_len))
+    }
+
+    /// Writes `data` as a single packet, prepending `hdr` as its virtio-net
+    /// header. Only valid for devices created with a virtio-net header and
+    /// without packet info.
+    pub fn write_with_vnet_hdr(&mut self, hdr: &VnetHdr, data: &[u8]) -> io::Result<()> {
+        self.check_writable()?;
+        let hdr_len = self.check_vnet_hdr()?;
+
+        let mut header = [0u8; VnetHdr::SIZE + 2];
+        header[..VnetHdr::SIZE].copy_from_slice(&hdr.to_bytes());
+        let len = retry_eintr(|| {
+            self.write_fd_vectored(&[IoSlice::new(&header[..hdr_len]), IoSlice::new(data)])
+        })?;
+        if len != hdr_len + data.len() {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "Short write"));
+        }
+        Ok(())
+    }
+
+    /// Makes reads fail with `ErrorKind::TimedOut` when no packet arrives
+    /// within `timeout`. `None` lets reads block indefinitely; a zero
+    /// duration is rejected.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), TunTapError> {
+        if timeout.is_some_and(|dur| dur.is_zero()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Zero read timeout").into());
+        }
+        self.read_timeout.store(poll_timeout(timeout), Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        match self.read_timeout.load(Ordering::Relaxed) {
+            -1 => None,
+            millis => Some(Duration::from_millis(millis as u64)),
+        }
+    }
+
+    // tun fds ignore SO_RCVTIMEO, so a read timeout is a poll() up front.
+    // Every read goes through here first.
+    fn wait_for_read(&self) -> io::Result<()> {
+        self.check_readable()?;
+        let timeout = self.read_timeout.load(Ordering::Relaxed);
+        if timeout < 0 {
+            return Ok(());
+        }
+
+        if !self.poll_readable(timeout)? {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "Read timed out"));
+        }
+        Ok(())
+    }
+
+    /// Waits until a packet can be read without blocking, for at most
+    /// `timeout` (forever with `None`). Returns `false` if the timeout
+    /// expired first.
+    pub fn wait_readable(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        self.poll_readable(poll_timeout(timeout))
+    }
+
+    fn poll_readable(&self, timeout: c_int) -> io::Result<bool> {
+        let mut fds = pollfd {
+            fd: self.file.as_raw_fd(),
+            events: POLLIN,
+            revents: 0,
+        };
+        let res = unsafe { poll(&mut fds, 1, timeout) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(res > 0)
+    }
+
+    fn check_vnet_hdr(&self) -> io::Result<usize> {
+        if self.if_flags & IFF_VNET_HDR == 0 || self.has_packet_info() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Device wasn't created with only a virtio-net header"));
+        }
+        Ok(self.vnet_hdr_len.load(Ordering::Relaxed))
+    }
+
+    // All packet I/O goes through these, to keep track of last_activity
+    pub(crate) fn read_fd(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        let len = (&self.file).read(buffer)?;
+        self.touch();
+        Ok(len)
+    }
+
+    fn read_fd_vectored(&self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        let len = (&self.file).read_vectored(bufs)?;
+        self.touch();
+        Ok(len)
+    }
+
+    pub(crate) fn write_fd(&self, data: &[u8]) -> io::Result<usize> {
+        let len = (&self.file).write(data)?;
+        self.touch();
+        Ok(len)
+    }
+
+    fn write_fd_vectored(&self, bufs: &[IoSlice]) -> io::Result<usize> {
+        let len = (&self.file).write_vectored(bufs)?;
+        self.touch();
+        Ok(len)
+    }
+
+    fn touch(&self) {
+        // Zero means no activity yet
+        let nanos = self.created.elapsed().as_nanos().min(u64::MAX as u128 - 1) as u64;
+        self.last_activity.store(nanos + 1, Ordering::Relaxed);
+    }
+
+    fn check_readable(&self) -> io::Result<()> {
+        if self.mode == OpenMode::WriteOnly {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied,
+                                      "Device was opened write-only"));
+        }
+        Ok(())
+    }
+
+    fn check_writable(&self) -> io::Result<()> {
+        if self.mode == OpenMode::ReadOnly {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied,
+                                      "Device was opened read-only"));
+        }
+        Ok(())
+    }
+
+    fn check_packet_info(&self) -> io::Result<()> {
+        if !self.has_packet_info() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Device was created without packet info"));
+        }
+        Ok(())
+    }
+
+    /// Reads a single packet scattered across `bufs` with one readv call.
+    pub fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        retry_eintr(|| {
+            self.wait_for_read()?;
+            self.read_fd_vectored(bufs)
+        })
+    }
+
+    /// Writes `bufs` as a single packet with one writev call.
+    pub fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        self.check_writable()?;
+        retry_eintr(|| self.write_fd_vectored(bufs))
+    }
+
+    /// Every write goes to the kernel as one packet straight away, nothing is
+    /// buffered in between, so this only exists for callers generic over
+    /// `Write` and never has anything to push.
+    pub fn flush(&mut self) -> io::Result<()> {
+        (&self.file).flush()
+    }
+}
+
+/// Checks that `name` would be accepted as a device name (by `TunTap::new`
+/// and the builder) without creating anything. An empty name is valid and
+/// lets the kernel pick one.
+pub fn validate_name(name: &str) -> Result<(), TunTapError> {
+    TunTap::ifr_name(name).map(|_| ())
+}
+
+/// Lists the tun and tap interfaces on the system, including persistent
+/// ones nobody is attached to, sorted by name.
+pub fn list_devices() -> Result<Vec<(String, TunTapType)>, TunTapError> {
+    let mut devices = Vec::new();
+    for entry in fs::read_dir(SYS_CLASS_NET)? {
+        let entry = entry?;
+        // Only tun devices have tun_flags, holding the TUNSETIFF flags in hex
+        let flags = match fs::read_to_string(entry.path().join("tun_flags")) {
+            Ok(flags) => flags,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let flags = flags.trim().trim_start_matches("0x");
+        let typ = match c_short::from_str_radix(flags, 16) {
+            Ok(flags) if flags & IFF_TAP != 0 => TunTapType::Tap,
+            Ok(_) => TunTapType::Tun,
+            Err(_) => continue,
+        };
+        devices.push((entry.file_name().to_string_lossy().into_owned(), typ));
+    }
+    devices.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(devices)
+}
+
+// Runs `op` again for as long as a signal interrupts it. Every packet read
+// and write goes through here so none of them surface `Interrupted`
+fn retry_eintr<T, F: FnMut() -> io::Result<T>>(mut op: F) -> io::Result<T> {
+    loop {
+        match op() {
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            res => return res,
+        }
+    }
+}
+
+// Converts to poll()'s milliseconds, rounding up so short timeouts don't
+// turn into a zero-length poll
+fn poll_timeout(timeout: Option<Duration>) -> c_int {
+    match timeout {
+        Some(dur) => dur.as_nanos().div_ceil(1_000_000).min(c_int::MAX as u128) as c_int,
+        None => -1,
+    }
+}
+
+fn ipv4_netmask(prefix_len: u8) -> Result<Ipv4Addr, TunTapError> {
+    if prefix_len > 32 {
+        return Err(TunTapError::InvalidPrefixLength { len: prefix_len, max: 32 });
+    }
+    Ok(Ipv4Addr::from(match prefix_len {
+        0 => 0,
+        len => !0u32 << (32 - len),
+    }))
+}
+
+fn ipv6_in6_addr(addr: Ipv6Addr) -> in6_addr {
+    let mut in6: in6_addr = unsafe { mem::zeroed() };
+    in6.s6_addr = addr.octets();
+    in6
+}
+
+// s_addr holds the octets in network order, whatever the host's byte order
+fn ipv4_sockaddr(addr: Ipv4Addr) -> sockaddr_in {
+    sockaddr_in {
+        sin_family: AF_INET as sa_family_t,
+        sin_port: 0,
+        sin_addr: in_addr {
+            s_addr: u32::from_ne_bytes(addr.octets()),
+        },
+        sin_zero: [0; 8],
+    }
+}
+
+fn sockaddr_ipv4(sock_addr: &sockaddr_in) -> Ipv4Addr {
+    Ipv4Addr::from(sock_addr.sin_addr.s_addr.to_ne_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use super::{ipv4_sockaddr, sockaddr_ipv4};
+
+    #[test]
+    fn ipv4_sockaddr_in_network_order() {
+        let addr = Ipv4Addr::new(10, 213, 7, 1);
+        let sa = ipv4_sockaddr(addr);
+        // s_addr holds the octets in memory order, whatever the host's
+        assert_eq!(sa.sin_addr.s_addr.to_ne_bytes(), addr.octets());
+        assert_eq!(sockaddr_ipv4(&sa), addr);
     }
 }
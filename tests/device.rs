@@ -0,0 +1,1213 @@
+#![cfg(target_os = "linux")]
+
+extern crate libc;
+extern crate tuntap;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, UdpSocket};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tuntap::raw;
+use tuntap::{list_devices, BufferedTunTap, BuildStatus, Duplex, EthernetFrame, InterfaceStats,
+             IpVersion, LinkInfo, OpenMode, PacketBatch, SockFilter, TunTap, TunTapBuilder,
+             TunTapConfig, TunTapError, TunTapShared, Tun, Tap};
+
+// Creating devices needs CAP_NET_ADMIN, so everything here is skipped
+// when the tests don't run as root.
+fn privileged() -> bool {
+    if unsafe { libc::geteuid() } != 0 {
+        eprintln!("skipping, not running as root");
+        return false;
+    }
+    true
+}
+
+// Clears CAP_NET_ADMIN from the calling thread only, leaving the other
+// tests running in parallel unaffected
+fn drop_net_admin() {
+    #[repr(C)]
+    struct CapHeader {
+        version: u32,
+        pid: libc::c_int,
+    }
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct CapData {
+        effective: u32,
+        permitted: u32,
+        inheritable: u32,
+    }
+    const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+    const CAP_NET_ADMIN: u32 = 12;
+
+    let mut header = CapHeader { version: LINUX_CAPABILITY_VERSION_3, pid: 0 };
+    let mut data = [CapData { effective: 0, permitted: 0, inheritable: 0 }; 2];
+    unsafe {
+        assert_eq!(libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()), 0);
+        data[0].effective &= !(1 << CAP_NET_ADMIN);
+        assert_eq!(libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()), 0);
+    }
+}
+
+#[test]
+fn reattach_to_up_device() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest0").unwrap();
+    tun.set_persistent(true).unwrap();
+    drop(tun);
+
+    // The interface is still up from the first attach
+    let tun = TunTap::new(Tun, "tttest0").unwrap();
+    tun.set_persistent(false).unwrap();
+}
+
+#[test]
+fn kernel_assigned_name() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest%d").unwrap();
+    let name = tun.get_name();
+    assert!(name.starts_with("tttest"));
+    assert!(!name.contains('%'));
+    assert!(Path::new("/sys/class/net").join(&name).exists());
+}
+
+#[test]
+fn index_matches_sysfs() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest1").unwrap();
+    let path = Path::new("/sys/class/net/tttest1/ifindex");
+    let index = fs::read_to_string(path).unwrap();
+    assert_eq!(tun.get_index().unwrap(), index.trim().parse::<i32>().unwrap());
+}
+
+#[test]
+fn read_times_out() {
+    if !privileged() {
+        return;
+    }
+
+    let mut tun = TunTap::new(Tun, "tttest2").unwrap();
+    tun.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    let mut buf = [0u8; 1500];
+    // Skip whatever the kernel sends on its own when the link comes up
+    let err = (0..100).filter_map(|_| tun.read(&mut buf).err()).next().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+}
+
+#[test]
+fn clone_shares_device() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest3").unwrap();
+    let clone = tun.try_clone().unwrap();
+    assert_eq!(clone.get_name(), "tttest3");
+    assert_ne!(clone.as_raw_fd(), tun.as_raw_fd());
+    drop(tun);
+    // The clone keeps the device alive
+    assert!(Path::new("/sys/class/net/tttest3").exists());
+}
+
+#[test]
+fn invalid_names_rejected() {
+    // Validation happens before the device is opened, so no root needed
+    for name in &["a/b", "a b", "tun:0", "tun\t", "tun\x07", "..", "tun\0"] {
+        match TunTap::new(Tun, name) {
+            Err(TunTapError::InvalidName) => {}
+            other => panic!("{:?} gave {:?}", name, other),
+        }
+    }
+}
+
+#[test]
+fn validate_name_checks_up_front() {
+    for name in &["", "tun0", "ttname%d"] {
+        tuntap::validate_name(name).unwrap();
+    }
+    let longest = "t".repeat(tuntap::MAX_NAME_LEN);
+    tuntap::validate_name(&longest).unwrap();
+    match tuntap::validate_name(&format!("{}t", longest)) {
+        Err(TunTapError::NameTooLong { max }) => assert_eq!(max, tuntap::MAX_NAME_LEN),
+        other => panic!("{:?}", other),
+    }
+    match tuntap::validate_name("a/b") {
+        Err(TunTapError::InvalidName) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn missing_device_path() {
+    match TunTap::with_device_path("/nonexistent/tun", Tun, "tttest4") {
+        Err(TunTapError::DeviceNodeMissing { path, source }) => {
+            assert_eq!(path, Path::new("/nonexistent/tun"));
+            assert_eq!(source.kind(), io::ErrorKind::NotFound);
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn device_node_already_there() {
+    if !privileged() {
+        return;
+    }
+
+    TunTap::ensure_device_node().unwrap();
+    assert!(Path::new("/dev/net/tun").exists());
+}
+
+#[test]
+fn mac_requires_tap() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest5").unwrap();
+    assert_eq!(tun.get_type(), Tun);
+    match tun.set_mac([0x02, 0, 0, 0, 0, 1]) {
+        Err(TunTapError::RequiresTap) => {}
+        other => panic!("{:?}", other),
+    }
+
+    let tap = TunTap::new(Tap, "tttest6").unwrap();
+    assert_eq!(tap.get_type(), Tap);
+    tap.set_mac([0x02, 0, 0, 0, 0, 1]).unwrap();
+    assert_eq!(tap.get_mac().unwrap(), [0x02, 0, 0, 0, 0, 1]);
+}
+
+#[test]
+fn statistics_from_fixture() {
+    let dir = env::temp_dir().join(format!("tuntap-stats-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let counters = ["rx_packets", "tx_packets", "rx_bytes", "tx_bytes", "rx_dropped", "tx_dropped"];
+    for (i, name) in counters.iter().enumerate() {
+        fs::write(dir.join(name), format!("{}\n", i + 1)).unwrap();
+    }
+
+    let stats = InterfaceStats::from_dir(&dir).unwrap();
+    assert_eq!(stats, InterfaceStats {
+        rx_packets: 1,
+        tx_packets: 2,
+        rx_bytes: 3,
+        tx_bytes: 4,
+        rx_dropped: 5,
+        tx_dropped: 6,
+    });
+
+    fs::remove_file(dir.join("tx_dropped")).unwrap();
+    assert!(InterfaceStats::from_dir(&dir).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn link_info_from_fixture() {
+    let dir = env::temp_dir().join(format!("tuntap-link-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("speed"), "-1\n").unwrap();
+    fs::write(dir.join("duplex"), "full\n").unwrap();
+
+    assert_eq!(LinkInfo::from_dir(&dir).unwrap(), LinkInfo {
+        speed: None,
+        duplex: Some(Duplex::Full),
+        carrier: None,
+    });
+    fs::write(dir.join("speed"), "1000\n").unwrap();
+    fs::write(dir.join("carrier"), "1\n").unwrap();
+    let info = LinkInfo::from_dir(&dir).unwrap();
+    assert_eq!((info.speed, info.carrier), (Some(1000), Some(true)));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ipv4_addr_in_network_order() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest7").unwrap();
+    let addr = Ipv4Addr::new(10, 213, 7, 1);
+    tun.add_ipv4_addr_with_prefix(addr, 24).unwrap();
+    assert_eq!(tun.get_ipv4_addr().unwrap(), addr);
+    // Binding only works if the kernel sees the same address, not a
+    // byte-swapped one
+    UdpSocket::bind((addr, 0)).unwrap();
+}
+
+#[test]
+fn filter_drops_frames() {
+    if !privileged() {
+        return;
+    }
+
+    let mut tap = TunTap::new(Tap, "tttest8").unwrap();
+    tap.add_ipv4_addr_with_prefix(Ipv4Addr::new(10, 213, 8, 1), 24).unwrap();
+    tap.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+    let sock = UdpSocket::bind((Ipv4Addr::new(10, 213, 8, 1), 0)).unwrap();
+    let mut buf = [0u8; 1600];
+
+    // BPF_RET | BPF_K with 0 accepts nothing
+    tap.attach_filter(&[SockFilter::new(0x06, 0, 0, 0)]).unwrap();
+    while tap.read(&mut buf).is_ok() {}
+    // Triggers an ARP request for the destination
+    sock.send_to(b"x", (Ipv4Addr::new(10, 213, 8, 2), 9)).unwrap();
+    assert_eq!(tap.read(&mut buf).unwrap_err().kind(), io::ErrorKind::TimedOut);
+
+    tap.detach_filter().unwrap();
+    sock.send_to(b"x", (Ipv4Addr::new(10, 213, 8, 3), 9)).unwrap();
+    tap.read(&mut buf).unwrap();
+}
+
+#[test]
+fn arp_request_frame() {
+    if !privileged() {
+        return;
+    }
+
+    let mut tap = TunTap::new(Tap, "tttest9").unwrap();
+    tap.add_ipv4_addr_with_prefix(Ipv4Addr::new(10, 213, 9, 1), 24).unwrap();
+    tap.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mac = tap.get_mac().unwrap();
+    let sock = UdpSocket::bind((Ipv4Addr::new(10, 213, 9, 1), 0)).unwrap();
+    sock.send_to(b"x", (Ipv4Addr::new(10, 213, 9, 2), 9)).unwrap();
+
+    let mut buf = [0u8; 1600];
+    let frame = loop {
+        let frame = tap.read_frame(&mut buf).unwrap();
+        // Skip IPv6 neighbour discovery and the like
+        if frame.ethertype == 0x0806 {
+            break frame;
+        }
+    };
+    assert_eq!(frame.dst, [0xff; 6]);
+    assert_eq!(frame.src, mac);
+    let reply = EthernetFrame { dst: mac, src: [0x02, 0, 0, 0, 0, 2], ..frame };
+    tap.write_frame(&reply).unwrap();
+}
+
+#[test]
+fn lists_ipv6_addrs() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest10").unwrap();
+    let addr: Ipv6Addr = "fd00:213:10::1".parse().unwrap();
+    tun.add_ipv6_addr_with_prefix(addr, 80).unwrap();
+    assert!(tun.get_ipv6_addrs().unwrap().contains(&(addr, 80)));
+
+    tun.remove_ipv6_addr(addr, 80).unwrap();
+    assert!(!tun.get_ipv6_addrs().unwrap().iter().any(|&(a, _)| a == addr));
+}
+
+#[test]
+fn alias_round_trip() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest11").unwrap();
+    tun.set_alias("tuntap test device").unwrap();
+    assert_eq!(tun.get_alias().unwrap(), "tuntap test device");
+    tun.set_alias("").unwrap();
+    assert_eq!(tun.get_alias().unwrap(), "");
+}
+
+#[test]
+fn rename_up_device() {
+    if !privileged() {
+        return;
+    }
+
+    let mut tun = TunTap::new(Tun, "tttest12").unwrap();
+    tun.rename("tttest12r%d").unwrap();
+    assert_eq!(tun.get_name(), "tttest12r0");
+    assert!(tun.is_up().unwrap());
+    assert!(Path::new("/sys/class/net/tttest12r0").exists());
+
+    let other = TunTap::new(Tun, "tttest13").unwrap();
+    assert!(tun.rename("tttest13").is_err());
+    assert_eq!(tun.get_name(), "tttest12r0");
+    drop(other);
+}
+
+#[test]
+fn lists_devices() {
+    if !privileged() {
+        return;
+    }
+
+    let _tun = TunTap::new(Tun, "tttest14").unwrap();
+    let _tap = TunTap::new(Tap, "tttest15").unwrap();
+    let devices = list_devices().unwrap();
+    assert!(devices.contains(&("tttest14".to_string(), Tun)));
+    assert!(devices.contains(&("tttest15".to_string(), Tap)));
+    assert!(!devices.iter().any(|(name, _)| name == "lo"));
+}
+
+#[test]
+fn query_reports_creation_flags() {
+    if !privileged() {
+        return;
+    }
+
+    let tap = TunTap::new(Tap, "tttest16").unwrap();
+    assert_eq!(TunTap::decode_iff(tap.query_iff().unwrap()), (Tap, false));
+    let tun = TunTap::new(Tun, "tttest17").unwrap();
+    assert_eq!(TunTap::decode_iff(tun.query_iff().unwrap()), (Tun, false));
+}
+
+#[test]
+fn read_into_batch_fills_slots() {
+    if !privileged() {
+        return;
+    }
+
+    let mut tun = TunTap::new(Tun, "tttest18").unwrap();
+    let addr = Ipv4Addr::new(10, 213, 18, 1);
+    tun.add_ipv4_addr_with_prefix(addr, 24).unwrap();
+    tun.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    let sock = UdpSocket::bind((addr, 0)).unwrap();
+    for _ in 0..3 {
+        sock.send_to(b"ping", (Ipv4Addr::new(10, 213, 18, 2), 9)).unwrap();
+    }
+
+    let mut batch = PacketBatch::new(8, tun.recommended_buffer_size());
+    let mut seen = 0;
+    while seen < 3 {
+        tun.read_into_batch(&mut batch).unwrap();
+        assert!(!batch.is_empty());
+        seen += batch.iter().filter(|p| p.len() == 32 && p.ends_with(b"ping")).count();
+    }
+}
+
+#[test]
+fn into_inner_keeps_device() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest19").unwrap();
+    let file = tun.into_inner();
+    assert!(Path::new("/sys/class/net/tttest19").exists());
+    drop(file);
+    assert!(!Path::new("/sys/class/net/tttest19").exists());
+
+    TunTap::new(Tun, "tttest19").unwrap().close().unwrap();
+    assert!(!Path::new("/sys/class/net/tttest19").exists());
+}
+
+#[test]
+fn exclusive_refuses_existing_device() {
+    if !privileged() {
+        return;
+    }
+
+    let _tun = TunTap::new(Tun, "tttest20").unwrap();
+    match TunTapBuilder::new().name("tttest20").exclusive(true).build() {
+        Err(TunTapError::Ioctl { ref source, .. }) => {
+            assert_eq!(source.raw_os_error(), Some(libc::EBUSY))
+        }
+        other => panic!("expected EBUSY, got {:?}", other),
+    }
+}
+
+#[test]
+fn sndbuf_round_trip() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest21").unwrap();
+    tun.set_sndbuf(65536).unwrap();
+    assert_eq!(tun.get_sndbuf().unwrap(), 65536);
+    assert!(tun.set_sndbuf(0).is_err());
+}
+
+#[test]
+fn write_packet_reports_length() {
+    if !privileged() {
+        return;
+    }
+
+    let mut tun = TunTap::new(Tun, "tttest22").unwrap();
+    // An IPv4 header with no payload, from 10.213.22.2 to 10.213.22.1
+    let packet = [0x45, 0, 0, 20, 0, 0, 0, 0, 64, 17, 0, 0,
+                  10, 213, 22, 2, 10, 213, 22, 1];
+    assert_eq!(tun.write_packet(&packet).unwrap(), packet.len());
+}
+
+#[test]
+fn from_file_creates_device() {
+    if !privileged() {
+        return;
+    }
+
+    let file = fs::OpenOptions::new().read(true).write(true).open("/dev/net/tun").unwrap();
+    let tap = TunTap::from_file(file, Tap, "tttest23").unwrap();
+    assert_eq!(tap.get_type(), Tap);
+    assert!(tap.is_up().unwrap());
+}
+
+#[test]
+fn cloexec_by_default() {
+    if !privileged() {
+        return;
+    }
+
+    let cloexec = |fd| unsafe { libc::fcntl(fd, libc::F_GETFD) } & libc::FD_CLOEXEC != 0;
+    let tun = TunTap::new(Tun, "tttest24").unwrap();
+    assert!(cloexec(tun.as_raw_fd()));
+    drop(tun);
+    let tun = TunTapBuilder::new().name("tttest24").cloexec(false).build().unwrap();
+    assert!(!cloexec(tun.as_raw_fd()));
+}
+
+#[test]
+fn ipv4_alias_adds_secondary_address() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest25").unwrap();
+    tun.add_ipv4_addr_with_prefix(Ipv4Addr::new(10, 213, 25, 1), 24).unwrap();
+    let alias = Ipv4Addr::new(10, 213, 26, 1);
+    tun.add_ipv4_alias("1", alias, 24).unwrap();
+    assert_eq!(tun.get_ipv4_addr().unwrap(), Ipv4Addr::new(10, 213, 25, 1));
+    UdpSocket::bind((alias, 0)).unwrap();
+
+    assert!(tun.add_ipv4_alias("a:b", alias, 24).is_err());
+    assert!(tun.add_ipv4_alias("toolong", alias, 24).is_err());
+}
+
+#[test]
+fn split_halves_across_threads() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest26").unwrap();
+    let addr = Ipv4Addr::new(10, 213, 26, 1);
+    tun.add_ipv4_addr_with_prefix(addr, 24).unwrap();
+    tun.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    let (reader, mut writer) = tun.split().unwrap();
+
+    let sock = UdpSocket::bind((addr, 7)).unwrap();
+    sock.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    // A UDP datagram from 10.213.26.2:9 to port 7, checksum left out
+    let packet = [0x45, 0, 0, 30, 0, 0, 0, 0, 64, 17, 0, 0,
+                  10, 213, 26, 2, 10, 213, 26, 1,
+                  0, 9, 0, 7, 0, 10, 0, 0, b'h', b'i'];
+    let packet = ipv4_with_checksum(&packet);
+    let writer = thread::spawn(move || writer.write(&packet).unwrap());
+
+    let mut buf = [0u8; 16];
+    let (len, _) = sock.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"hi");
+    writer.join().unwrap();
+    assert!(reader.get_ref().is_up().unwrap());
+}
+
+#[test]
+fn shared_splits_from_other_threads() {
+    if !privileged() {
+        return;
+    }
+
+    let shared = Arc::new(TunTapShared::new(TunTap::new(Tun, "tttest62").unwrap()));
+    let addr = Ipv4Addr::new(10, 213, 62, 1);
+    shared.get_ref().add_ipv4_addr_with_prefix(addr, 24).unwrap();
+
+    let sock = UdpSocket::bind((addr, 7)).unwrap();
+    sock.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    let packet = [0x45, 0, 0, 30, 0, 0, 0, 0, 64, 17, 0, 0,
+                  10, 213, 62, 2, 10, 213, 62, 1,
+                  0, 9, 0, 7, 0, 10, 0, 0, b'h', b'i'];
+    let packet = ipv4_with_checksum(&packet);
+    let handle = shared.clone();
+    let writer = thread::spawn(move || {
+        let (_, mut writer) = handle.split().unwrap();
+        writer.write(&packet).unwrap();
+    });
+
+    let mut buf = [0u8; 16];
+    let (len, _) = sock.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"hi");
+    writer.join().unwrap();
+    // The halves were dropped with the thread, the shared handle lives on
+    assert!(shared.get_ref().is_up().unwrap());
+}
+
+fn ipv4_with_checksum(packet: &[u8]) -> Vec<u8> {
+    let mut packet = packet.to_vec();
+    let mut sum = 0u32;
+    for chunk in packet[..20].chunks(2) {
+        sum += u32::from(u16::from_be_bytes([chunk[0], chunk[1]]));
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    packet[10..12].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+    packet
+}
+
+#[test]
+fn raw_sockaddr_used_as_is() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest27").unwrap();
+    let mut sa: libc::sockaddr_in = unsafe { std::mem::zeroed() };
+    sa.sin_family = libc::AF_INET as libc::sa_family_t;
+    // 10.213.27.1 in network order
+    sa.sin_addr.s_addr = 0x0ad5_1b01u32.to_be();
+    tun.add_ipv4_sockaddr(sa).unwrap();
+    assert_eq!(tun.get_ipv4_addr().unwrap(), Ipv4Addr::new(10, 213, 27, 1));
+}
+
+#[test]
+fn debug_shows_type() {
+    if !privileged() {
+        return;
+    }
+
+    let tap = TunTap::new(Tap, "tttest28").unwrap();
+    assert_eq!(tap.to_string(), "tttest28");
+    let debug = format!("{:?}", tap);
+    assert!(debug.contains("\"tttest28\"") && debug.contains("Tap"), "{}", debug);
+    assert!(debug.contains(&format!("fd: {}", tap.as_raw_fd())), "{}", debug);
+}
+
+#[test]
+fn routes_added_and_removed() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest29").unwrap();
+    tun.add_ipv4_addr_with_prefix(Ipv4Addr::new(10, 213, 29, 1), 24).unwrap();
+    tun.add_ipv6_addr_with_prefix("fd00:213:29::1".parse().unwrap(), 64).unwrap();
+    let routes = |path| -> Vec<String> {
+        fs::read_to_string(path).unwrap().lines()
+            .filter(|line| line.contains("tttest29"))
+            .map(String::from)
+            .collect()
+    };
+
+    // 10.213.30.0, in /proc/net/route's host-order hex
+    let dest = IpAddr::V4(Ipv4Addr::new(10, 213, 30, 7));
+    let gateway = Some(IpAddr::V4(Ipv4Addr::new(10, 213, 29, 2)));
+    tun.add_route(dest, 24, gateway).unwrap();
+    assert!(routes("/proc/net/route").iter().any(|r| r.contains("001ED50A")));
+    tun.del_route(dest, 24, gateway).unwrap();
+    assert!(!routes("/proc/net/route").iter().any(|r| r.contains("001ED50A")));
+
+    let dest = IpAddr::V6("fd00:213:30::".parse().unwrap());
+    tun.add_route(dest, 48, None).unwrap();
+    assert!(routes("/proc/net/ipv6_route").iter().any(|r| r.starts_with("fd000213003")));
+    tun.del_route(dest, 48, None).unwrap();
+    assert!(!routes("/proc/net/ipv6_route").iter().any(|r| r.starts_with("fd000213003")));
+
+    assert!(tun.add_route(dest, 48, gateway).is_err());
+}
+
+#[test]
+fn name_matches_ip_link() {
+    if !privileged() {
+        return;
+    }
+
+    // `ip -o link` prints one "<index>: <name>: <flags> ..." line per interface
+    let ip_link_names = || -> Option<Vec<String>> {
+        let output = process::Command::new("ip").args(["-o", "link", "show"]).output().ok()?;
+        Some(String::from_utf8(output.stdout).unwrap().lines()
+             .filter_map(|line| line.split(": ").nth(1))
+             .map(|name| name.split('@').next().unwrap().to_string())
+             .collect())
+    };
+
+    let explicit = TunTap::new(Tap, "tttest30").unwrap();
+    // A prefix of its own, so the kernel can't pick a name another test uses
+    let wildcard = TunTap::new(Tun, "ttname%d").unwrap();
+    let built = TunTapBuilder::new().name("ttname%d").typ(Tap).build().unwrap();
+    let names = match ip_link_names() {
+        Some(names) => names,
+        None => return eprintln!("skipping, ip not available"),
+    };
+    for tuntap in &[explicit, wildcard, built] {
+        assert!(names.contains(&tuntap.get_name()), "{} not in {:?}", tuntap, names);
+        assert!(tuntap.is_up().unwrap());
+    }
+}
+
+#[test]
+fn from_config_applies_settings() {
+    if !privileged() {
+        return;
+    }
+
+    let config = TunTapConfig {
+        name: "tttest31".to_string(),
+        typ: Tap,
+        mtu: Some(1400),
+        addresses: vec![(IpAddr::V4(Ipv4Addr::new(10, 213, 31, 1)), 24),
+                        (IpAddr::V6("fd00:213:31::1".parse().unwrap()), 64)],
+        mac: Some([0x02, 0, 0, 0, 0x31, 1]),
+    };
+    let tap = TunTap::from_config(&config).unwrap();
+    assert_eq!(tap.get_mtu().unwrap(), 1400);
+    assert_eq!(tap.get_mac().unwrap(), [0x02, 0, 0, 0, 0x31, 1]);
+    assert_eq!(tap.get_ipv4_addr().unwrap(), Ipv4Addr::new(10, 213, 31, 1));
+    assert!(tap.is_up().unwrap());
+}
+
+#[test]
+fn read_ip_packet_classifies() {
+    if !privileged() {
+        return;
+    }
+
+    let mut tun = TunTap::new(Tun, "tttest32").unwrap();
+    let addr = Ipv4Addr::new(10, 213, 32, 1);
+    tun.add_ipv4_addr_with_prefix(addr, 24).unwrap();
+    tun.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    UdpSocket::bind((addr, 0)).unwrap().send_to(b"ping", (Ipv4Addr::new(10, 213, 32, 2), 9))
+        .unwrap();
+
+    // Router solicitations and the like may come first
+    let mut buf = [0u8; 1500];
+    while tun.read_ip_packet(&mut buf).unwrap() != (IpVersion::V4, 32) {}
+    assert!(TunTap::new(Tap, "tttest33").unwrap().read_ip_packet(&mut buf).is_err());
+}
+
+#[test]
+fn failed_build_removes_created_device() {
+    if !privileged() {
+        return;
+    }
+
+    let exists = || Path::new("/sys/class/net/tttest34").exists();
+    // The kernel refuses MTUs this small
+    let res = TunTapBuilder::new().name("tttest34").persistent(true).mtu(10).build();
+    assert!(res.is_err());
+    assert!(!exists());
+
+    let tun = TunTap::new(Tun, "tttest34").unwrap();
+    tun.set_persistent(true).unwrap();
+    drop(tun);
+    let res = TunTapBuilder::new().name("tttest34").persistent(true).mtu(10).build();
+    assert!(res.is_err());
+    assert!(exists());
+    TunTap::new(Tun, "tttest34").unwrap().set_persistent(false).unwrap();
+}
+
+#[test]
+fn link_info_reports_carrier() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest35").unwrap();
+    assert_eq!(tun.get_link_info().unwrap().carrier, Some(true));
+}
+
+extern "C" fn ignore_signal(_: libc::c_int) {}
+
+#[test]
+fn reads_retry_after_signals() {
+    if !privileged() {
+        return;
+    }
+
+    // Without SA_RESTART, a signal fails the blocked poll() with EINTR
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = ignore_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        assert_eq!(libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut()), 0);
+    }
+    let reader = unsafe { libc::pthread_self() } as usize;
+    let interrupt = move || thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        unsafe { libc::pthread_kill(reader as libc::pthread_t, libc::SIGUSR1) };
+    });
+
+    // Left down, so the kernel doesn't send anything to read
+    let mut tun = TunTapBuilder::new().name("tttest36").bring_up(false).build().unwrap();
+    tun.set_read_timeout(Some(Duration::from_millis(300))).unwrap();
+    let mut buf = [0u8; 1500];
+    let signal = interrupt();
+    let err = tun.read_interruptible(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    signal.join().unwrap();
+
+    let signal = interrupt();
+    let err = tun.read(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    signal.join().unwrap();
+}
+
+#[test]
+fn open_mode_restricts_io() {
+    if !privileged() {
+        return;
+    }
+
+    let mut reader = TunTapBuilder::new().name("tttest37").open_mode(OpenMode::ReadOnly).build()
+        .unwrap();
+    let err = reader.write(&[0x45; 20]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+    let mut writer = TunTapBuilder::new().name("tttest38").open_mode(OpenMode::WriteOnly).build()
+        .unwrap();
+    let err = writer.read(&mut [0; 1500]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    let packet = [0x45, 0, 0, 20, 0, 0, 0, 0, 64, 17, 0, 0,
+                  10, 213, 38, 2, 10, 213, 38, 1];
+    assert_eq!(writer.write_packet(&packet).unwrap(), 20);
+    let err = writer.try_clone().unwrap().read(&mut [0; 1500]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+}
+
+#[test]
+fn raw_ioctl_reads_index() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest39").unwrap();
+    let mut req = raw::ioctl_ifindex_data {
+        ifr_name: tun.ifr_name_bytes(),
+        ifr_ifindex: 0,
+        ifr_pad: [0; raw::IFREQ_PAD],
+    };
+    unsafe { tun.ioctl_raw(libc::SIOCGIFINDEX, &mut req).unwrap() };
+    assert_eq!(req.ifr_ifindex, tun.get_index().unwrap());
+}
+
+#[test]
+fn drain_discards_queued_packets() {
+    if !privileged() {
+        return;
+    }
+
+    let mut tun = TunTap::new(Tun, "tttest40").unwrap();
+    let addr = Ipv4Addr::new(10, 213, 40, 1);
+    tun.add_ipv4_addr_with_prefix(addr, 24).unwrap();
+    let sock = UdpSocket::bind((addr, 0)).unwrap();
+    for _ in 0..3 {
+        sock.send_to(b"ping", (Ipv4Addr::new(10, 213, 40, 2), 9)).unwrap();
+    }
+
+    assert!(tun.drain().unwrap() >= 3);
+}
+
+#[test]
+fn last_activity_follows_io() {
+    if !privileged() {
+        return;
+    }
+
+    let mut tun = TunTap::new(Tun, "tttest41").unwrap();
+    assert!(tun.last_activity().is_none());
+    let before = Instant::now();
+    let packet = [0x45, 0, 0, 20, 0, 0, 0, 0, 64, 17, 0, 0,
+                  10, 213, 41, 2, 10, 213, 41, 1];
+    tun.write_packet(&packet).unwrap();
+    let last = tun.last_activity().unwrap();
+    assert!(last >= before && last <= Instant::now());
+}
+
+#[test]
+fn ipv6_addr_skips_link_local() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest42").unwrap();
+    let addr: Ipv6Addr = "fd00:213:42::1".parse().unwrap();
+    tun.add_ipv6_addr(addr).unwrap();
+    assert_eq!(tun.get_ipv6_addr().unwrap(), Some((addr, 64)));
+    if let Some((link_local, _)) = tun.get_ipv6_link_local().unwrap() {
+        assert!(link_local.is_unicast_link_local());
+    }
+
+    tun.remove_ipv6_addr(addr, 64).unwrap();
+    assert_eq!(tun.get_ipv6_addr().unwrap(), None);
+}
+
+#[test]
+fn buffered_reads_reuse_buffer() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest43").unwrap();
+    let addr = Ipv4Addr::new(10, 213, 43, 1);
+    tun.add_ipv4_addr_with_prefix(addr, 24).unwrap();
+    tun.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let sock = UdpSocket::bind((addr, 0)).unwrap();
+
+    // Every packet is bigger than a bare IPv4 header
+    let mut small = BufferedTunTap::with_capacity(tun, 20);
+    assert_eq!(small.capacity(), 20);
+    sock.send_to(b"ping", (Ipv4Addr::new(10, 213, 43, 2), 9)).unwrap();
+    assert_eq!(small.next_packet().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    let mut buffered = BufferedTunTap::new(small.into_inner());
+    assert_eq!(buffered.capacity(), buffered.get_ref().recommended_buffer_size());
+    sock.send_to(b"ping", (Ipv4Addr::new(10, 213, 43, 2), 9)).unwrap();
+    loop {
+        let packet = buffered.next_packet().unwrap();
+        if tuntap::detect_ip_version(packet) == Some(IpVersion::V4) {
+            assert!(packet.ends_with(b"ping"));
+            break;
+        }
+    }
+}
+
+#[test]
+fn read_exact_frame_reports_truncation() {
+    if !privileged() {
+        return;
+    }
+
+    let mut tun = TunTap::new(Tun, "tttest44").unwrap();
+    let addr = Ipv4Addr::new(10, 213, 44, 1);
+    tun.add_ipv4_addr_with_prefix(addr, 24).unwrap();
+    tun.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let sock = UdpSocket::bind((addr, 0)).unwrap();
+    sock.send_to(b"ping", (Ipv4Addr::new(10, 213, 44, 2), 9)).unwrap();
+
+    let err = tun.read_exact_frame(&mut [0; 20]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    match err.get_ref().and_then(|err| err.downcast_ref::<TunTapError>()) {
+        Some(&TunTapError::FrameTruncated { len }) => assert_eq!(len, 20),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn tap_joins_and_leaves_bridge() {
+    if !privileged() {
+        return;
+    }
+
+    let ip_link = |args: &[&str]| {
+        process::Command::new("ip").arg("link").args(args).status().map(|s| s.success())
+    };
+    // Left over from an earlier run that failed halfway
+    let _ = ip_link(&["del", "ttbr45"]);
+    match ip_link(&["add", "name", "ttbr45", "type", "bridge"]) {
+        Ok(true) => {}
+        // No ip tool or no bridge support in this kernel
+        _ => return,
+    }
+
+    let tap = TunTap::new(Tap, "tttest45").unwrap();
+    let master = Path::new("/sys/class/net/tttest45/master");
+    tap.add_to_bridge("ttbr45").unwrap();
+    assert_eq!(fs::read_link(master).unwrap().file_name().unwrap(), "ttbr45");
+    tap.remove_from_bridge("ttbr45").unwrap();
+    assert!(!master.exists());
+
+    ip_link(&["del", "ttbr45"]).unwrap();
+    match TunTap::new(Tun, "tttest46").unwrap().add_to_bridge("ttbr45") {
+        Err(TunTapError::RequiresTap) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn buffered_writes_need_no_flush() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest47").unwrap();
+    let addr = Ipv4Addr::new(10, 213, 47, 1);
+    tun.add_ipv4_addr_with_prefix(addr, 24).unwrap();
+    let sock = UdpSocket::bind((addr, 9)).unwrap();
+    sock.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+
+    // UDP from 10.213.47.2:9 to port 9 without a checksum
+    let mut packet = vec![0x45, 0, 0, 32, 0, 0, 0, 0, 64, 17, 0, 0,
+                          10, 213, 47, 2, 10, 213, 47, 1];
+    packet.extend_from_slice(&[0, 9, 0, 9, 0, 12, 0, 0]);
+    packet.extend_from_slice(b"pong");
+    let packet = ipv4_with_checksum(&packet);
+    let mut buffered = BufferedTunTap::new(tun);
+    io::Write::write_all(&mut buffered, &packet).unwrap();
+    // Already delivered before the flush
+    let mut buf = [0u8; 16];
+    assert_eq!(sock.recv(&mut buf).unwrap(), 4);
+    io::Write::flush(&mut buffered).unwrap();
+}
+
+#[test]
+fn queues_poll_independently() {
+    if !privileged() {
+        return;
+    }
+
+    let first = TunTap::new_multi_queue(Tun, "tttest48").unwrap();
+    let mut queues = vec![first.add_queue().unwrap(), first.add_queue().unwrap()];
+    queues.insert(0, first);
+    let addr = Ipv4Addr::new(10, 213, 48, 1);
+    queues[0].add_ipv4_addr_with_prefix(addr, 24).unwrap();
+    let fds: Vec<_> = queues.iter().map(|queue| queue.as_raw_fd()).collect();
+    assert!(fds[0] != fds[1] && fds[1] != fds[2] && fds[0] != fds[2]);
+
+    // Different source ports hash to different queues
+    for _ in 0..16 {
+        let sock = UdpSocket::bind((addr, 0)).unwrap();
+        sock.send_to(b"ping", (Ipv4Addr::new(10, 213, 48, 2), 9)).unwrap();
+    }
+    let mut pollfds: Vec<_> = fds.iter()
+        .map(|&fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 })
+        .collect();
+    let ready = unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, 2000) };
+    assert!(ready > 0);
+    for (queue, pollfd) in queues.iter_mut().zip(&pollfds) {
+        if pollfd.revents & libc::POLLIN != 0 {
+            queue.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+            queue.read(&mut [0; 1500]).unwrap();
+        }
+    }
+
+    match TunTap::new(Tun, "tttest49").unwrap().add_queue() {
+        Err(TunTapError::Ioctl { call: "TUNSETIFF", .. }) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn noarp_defaults_on_for_tun() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTapBuilder::new().name("tttest50").build().unwrap();
+    assert!(tun.is_noarp().unwrap());
+    let arp = TunTapBuilder::new().name("tttest51").noarp(false).build().unwrap();
+    assert!(!arp.is_noarp().unwrap());
+    arp.set_noarp(true).unwrap();
+    assert!(arp.is_noarp().unwrap());
+
+    let tap = TunTapBuilder::new().name("tttest52").typ(Tap).build().unwrap();
+    assert!(!tap.is_noarp().unwrap());
+}
+
+#[test]
+fn queue_info_tracks_queues() {
+    if !privileged() {
+        return;
+    }
+
+    let first = TunTap::new_multi_queue(Tun, "tttest54").unwrap();
+    let second = first.add_queue().unwrap();
+    let third = second.add_queue().unwrap();
+    let info = first.queue_info().unwrap();
+    assert_eq!(info.enabled, [true, true, true]);
+    assert_eq!(info.active, 3);
+
+    second.set_queue_enabled(false).unwrap();
+    let info = third.queue_info().unwrap();
+    assert_eq!(info.enabled, [true, false, true]);
+    assert_eq!(info.active, 2);
+
+    // A clone is the same queue, dropping the original keeps it attached
+    let clone = third.try_clone().unwrap();
+    drop(third);
+    assert_eq!(first.queue_info().unwrap().enabled, [true, false, true]);
+    drop(clone);
+    assert_eq!(first.queue_info().unwrap().enabled, [true, false]);
+}
+
+#[test]
+fn set_addresses_rolls_back() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest55").unwrap();
+    let ipv4 = Ipv4Addr::new(10, 213, 55, 1);
+    let ipv6: Ipv6Addr = "fd00:213:55::1".parse().unwrap();
+    tun.set_addresses(&[(IpAddr::V4(ipv4), 24), (IpAddr::V6(ipv6), 64)]).unwrap();
+    assert_eq!(tun.get_ipv4_addr().unwrap(), ipv4);
+    assert_eq!(tun.get_ipv4_netmask().unwrap(), Ipv4Addr::new(255, 255, 255, 0));
+    assert_eq!(tun.get_ipv6_addr().unwrap(), Some((ipv6, 64)));
+
+    // Adding the IPv6 address a second time fails after the IPv4 one
+    // replaced the first
+    let other: Ipv6Addr = "fd00:213:55::2".parse().unwrap();
+    let addrs = [(IpAddr::V6(other), 64), (IpAddr::V4(Ipv4Addr::new(10, 213, 55, 9)), 16),
+                 (IpAddr::V6(ipv6), 64)];
+    assert!(tun.set_addresses(&addrs).is_err());
+    assert_eq!(tun.get_ipv4_addr().unwrap(), ipv4);
+    assert_eq!(tun.get_ipv4_netmask().unwrap(), Ipv4Addr::new(255, 255, 255, 0));
+    let ipv6_addrs = tun.get_ipv6_addrs().unwrap();
+    assert!(ipv6_addrs.contains(&(ipv6, 64)));
+    assert!(!ipv6_addrs.iter().any(|&(addr, _)| addr == other));
+
+    let two_ipv4 = [(IpAddr::V4(ipv4), 24), (IpAddr::V4(Ipv4Addr::new(10, 213, 55, 2)), 24)];
+    assert!(tun.set_addresses(&two_ipv4).is_err());
+}
+
+#[test]
+fn ip_packets_get_packet_info() {
+    if !privileged() {
+        return;
+    }
+
+    let mut tun = TunTapBuilder::new().name("tttest56").packet_info(true).build().unwrap();
+    assert!(tun.has_packet_info());
+    let addr = Ipv4Addr::new(10, 213, 56, 1);
+    tun.add_ipv4_addr_with_prefix(addr, 24).unwrap();
+    let sock = UdpSocket::bind((addr, 9)).unwrap();
+    sock.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+
+    // UDP from 10.213.56.2:9 to port 9 without a checksum
+    let mut packet = vec![0x45, 0, 0, 32, 0, 0, 0, 0, 64, 17, 0, 0,
+                          10, 213, 56, 2, 10, 213, 56, 1];
+    packet.extend_from_slice(&[0, 9, 0, 9, 0, 12, 0, 0]);
+    packet.extend_from_slice(b"pong");
+    tun.write_ip_packet(&ipv4_with_checksum(&packet)).unwrap();
+    let mut buf = [0u8; 16];
+    assert_eq!(sock.recv(&mut buf).unwrap(), 4);
+
+    let err = tun.write_ip_packet(&[0x50, 0, 0, 0]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(!TunTap::new(Tun, "tttest57").unwrap().has_packet_info());
+}
+
+#[test]
+fn best_effort_up_reports_eperm() {
+    if !privileged() {
+        return;
+    }
+
+    let tap = TunTap::new(Tap, "tttest58").unwrap();
+    tap.set_persistent(true).unwrap();
+    tap.bring_down().unwrap();
+    drop(tap);
+
+    thread::spawn(|| {
+        drop_net_admin();
+        let mut builder = TunTapBuilder::new();
+        builder.name("tttest58").typ(Tap);
+        match builder.build() {
+            Err(TunTapError::Ioctl { call: "SIOCSIFFLAGS", ref source })
+                if source.raw_os_error() == Some(libc::EPERM) => {}
+            other => panic!("{:?}", other),
+        }
+
+        let (tap, status): (TunTap, BuildStatus) =
+            builder.best_effort_up(true).build_with_status().unwrap();
+        assert!(!status.is_complete());
+        assert_eq!(status.up_error.unwrap().raw_os_error(), Some(libc::EPERM));
+        assert!(!tap.is_up().unwrap());
+    }).join().unwrap();
+
+    TunTap::new(Tap, "tttest58").unwrap().set_persistent(false).unwrap();
+}
+
+#[test]
+fn unprivileged_tun_reattach() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTap::new(Tun, "tttest59").unwrap();
+    tun.set_persistent(true).unwrap();
+    drop(tun);
+    let arp = TunTap::new(Tun, "tttest60").unwrap();
+    arp.set_persistent(true).unwrap();
+    arp.set_noarp(false).unwrap();
+    drop(arp);
+
+    thread::spawn(|| {
+        drop_net_admin();
+        // Up and IFF_NOARP already, so there's nothing to change
+        let (tun, status) = TunTapBuilder::new().name("tttest59").best_effort_up(true)
+            .build_with_status().unwrap();
+        assert!(status.is_complete());
+        assert!(tun.is_up().unwrap() && tun.is_noarp().unwrap());
+
+        let (arp, status) = TunTapBuilder::new().name("tttest60").best_effort_up(true)
+            .build_with_status().unwrap();
+        assert_eq!(status.noarp_error.unwrap().raw_os_error(), Some(libc::EPERM));
+        assert!(!arp.is_noarp().unwrap());
+    }).join().unwrap();
+
+    TunTap::new(Tun, "tttest59").unwrap().set_persistent(false).unwrap();
+    TunTap::new(Tun, "tttest60").unwrap().set_persistent(false).unwrap();
+}
+
+#[test]
+fn batch_capacity_is_slot_count() {
+    let batch = PacketBatch::new(3, 1500);
+    assert_eq!(batch.capacity(), 3);
+    assert_eq!(batch.slot_size(), 1500);
+}
+
+#[test]
+#[should_panic(expected = "overflows")]
+fn batch_size_overflow_panics() {
+    PacketBatch::new(usize::MAX / 2, 3);
+}
+
+#[test]
+fn queues_inherit_offloads() {
+    if !privileged() {
+        return;
+    }
+
+    let tun = TunTapBuilder::new().name("tttest61").multi_queue(true).vnet_hdr(true).build()
+        .unwrap();
+    tun.set_offload(tuntap::OffloadFlags::CSUM | tuntap::OffloadFlags::TSO4).unwrap();
+    let size = tun.recommended_buffer_size();
+    assert!(size > u16::MAX as usize);
+    assert_eq!(tun.add_queue().unwrap().recommended_buffer_size(), size);
+    assert_eq!(tun.try_clone().unwrap().recommended_buffer_size(), size);
+}
+
+#[test]
+fn foreign_vnet_hdr_size_is_rejected() {
+    if !privileged() {
+        return;
+    }
+
+    // _IOW('T', 216, int), the kernel takes any size of 10 or more
+    const TUNSETVNETHDRSZ: libc::c_ulong = 0x400454d8;
+    let tap = TunTapBuilder::new().name("tttest63").typ(Tap).vnet_hdr(true).build().unwrap();
+    let size: libc::c_int = 20;
+    assert_eq!(unsafe { libc::ioctl(tap.as_raw_fd(), TUNSETVNETHDRSZ, &size) }, 0);
+
+    match tap.get_vnet_hdr_size() {
+        Err(TunTapError::Io(ref err)) if err.kind() == io::ErrorKind::InvalidInput => {}
+        res => panic!("unexpected {:?}", res),
+    }
+    let fd = unsafe { libc::dup(tap.as_raw_fd()) };
+    assert!(fd >= 0);
+    match unsafe { TunTap::from_raw_fd(fd, "tttest63") } {
+        Err(TunTapError::Io(ref err)) if err.kind() == io::ErrorKind::InvalidInput => {}
+        res => panic!("unexpected {:?}", res),
+    }
+}
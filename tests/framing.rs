@@ -0,0 +1,41 @@
+extern crate tuntap;
+
+use tuntap::FrameCodec;
+
+#[test]
+fn round_trip() {
+    let mut stream = Vec::new();
+    FrameCodec::encode(b"first", &mut stream).unwrap();
+    FrameCodec::encode(b"", &mut stream).unwrap();
+    FrameCodec::encode(b"third", &mut stream).unwrap();
+    assert_eq!(&stream[..7], b"\x00\x05first");
+
+    assert_eq!(FrameCodec::decode(&mut stream).unwrap(), b"first");
+    assert_eq!(FrameCodec::decode(&mut stream).unwrap(), b"");
+    assert_eq!(FrameCodec::decode(&mut stream).unwrap(), b"third");
+    assert!(FrameCodec::decode(&mut stream).is_none());
+    assert!(stream.is_empty());
+}
+
+#[test]
+fn partial_frames() {
+    let mut encoded = Vec::new();
+    FrameCodec::encode(b"packet", &mut encoded).unwrap();
+
+    // Feed the frame a byte at a time, as a stream socket might
+    let mut buf = Vec::new();
+    for (i, byte) in encoded.iter().enumerate() {
+        assert!(FrameCodec::decode(&mut buf).is_none());
+        assert_eq!(buf.len(), i);
+        buf.push(*byte);
+    }
+    assert_eq!(FrameCodec::decode(&mut buf).unwrap(), b"packet");
+}
+
+#[test]
+fn oversized_packet() {
+    let mut out = Vec::new();
+    let packet = vec![0u8; FrameCodec::MAX_PACKET_SIZE + 1];
+    assert!(FrameCodec::encode(&packet, &mut out).is_err());
+    assert!(out.is_empty());
+}
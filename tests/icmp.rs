@@ -0,0 +1,111 @@
+#![cfg(target_os = "linux")]
+
+extern crate libc;
+extern crate tuntap;
+
+use std::env;
+use std::fs::OpenOptions;
+use std::net::Ipv4Addr;
+use std::process::Command;
+use std::time::Duration;
+use tuntap::{detect_ip_version, IpVersion, TunTap, Tun};
+
+// Set for the copy of a test that runs inside a new namespace
+const IN_NAMESPACE: &str = "TUNTAP_TEST_IN_NAMESPACE";
+
+// libtest's exit code when a test failed, as opposed to unshare failing
+const TEST_FAILED: i32 = 101;
+
+// Runs `test` directly as root. Otherwise re-runs only the test `name` of
+// this binary under `unshare`, as root of a new user and network namespace,
+// and skips it if the system doesn't allow unprivileged namespaces.
+fn as_root(name: &str, test: fn()) {
+    if unsafe { libc::geteuid() } == 0 {
+        return test();
+    }
+    assert!(env::var_os(IN_NAMESPACE).is_none(), "not root inside the namespace");
+    // Namespace root gets the capabilities, not access to the device node
+    if OpenOptions::new().read(true).write(true).open("/dev/net/tun").is_err() {
+        eprintln!("skipping, /dev/net/tun isn't accessible without root");
+        return;
+    }
+
+    let status = Command::new("unshare")
+        .args(["--user", "--map-root-user", "--net", "--"])
+        .arg(env::current_exe().unwrap())
+        .args([name, "--exact", "--nocapture"])
+        .env(IN_NAMESPACE, "1")
+        .status();
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) if status.code() == Some(TEST_FAILED) => panic!("{} failed in a namespace", name),
+        _ => eprintln!("skipping, neither root nor able to create a user namespace"),
+    }
+}
+
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum = 0u32;
+    for chunk in data.chunks(2) {
+        let word = if chunk.len() == 2 { [chunk[0], chunk[1]] } else { [chunk[0], 0] };
+        sum += u32::from(u16::from_be_bytes(word));
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+// An IPv4 packet carrying an ICMP echo request or reply
+fn icmp_echo(src: Ipv4Addr, dst: Ipv4Addr, typ: u8, id: u16, seq: u16, payload: &[u8]) -> Vec<u8> {
+    let mut icmp = vec![typ, 0, 0, 0];
+    icmp.extend_from_slice(&id.to_be_bytes());
+    icmp.extend_from_slice(&seq.to_be_bytes());
+    icmp.extend_from_slice(payload);
+    let sum = checksum(&icmp);
+    icmp[2..4].copy_from_slice(&sum.to_be_bytes());
+
+    let len = (20 + icmp.len()) as u16;
+    let mut packet = vec![0x45, 0];
+    packet.extend_from_slice(&len.to_be_bytes());
+    // No ID or fragmentation, TTL 64, protocol 1 (ICMP), checksum to fill in
+    packet.extend_from_slice(&[0, 0, 0, 0, 64, 1, 0, 0]);
+    packet.extend_from_slice(&src.octets());
+    packet.extend_from_slice(&dst.octets());
+    let sum = checksum(&packet);
+    packet[10..12].copy_from_slice(&sum.to_be_bytes());
+    packet.extend_from_slice(&icmp);
+    packet
+}
+
+fn icmp_echo_round_trip() {
+    let local = Ipv4Addr::new(10, 213, 53, 1);
+    let peer = Ipv4Addr::new(10, 213, 53, 2);
+    let mut tun = TunTap::new(Tun, "tttest53").unwrap();
+    tun.add_ipv4_addr_with_prefix(local, 24).unwrap();
+    assert_eq!(tun.get_ipv4_addr().unwrap(), local);
+    tun.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+
+    // The kernel answers pings to its own address back out of the device
+    let payload = b"tuntap round trip";
+    tun.write(&icmp_echo(peer, local, 8, 0x2135, 1, payload)).unwrap();
+    let expected = icmp_echo(local, peer, 0, 0x2135, 1, payload);
+    let mut buf = vec![0u8; tun.recommended_buffer_size()];
+    loop {
+        // Skip the IPv6 router solicitations the kernel sends on bring-up
+        let len = tun.read(&mut buf).unwrap();
+        if detect_ip_version(&buf[..len]) != Some(IpVersion::V4) {
+            continue;
+        }
+        let reply = &buf[..len];
+        // Protocol, addresses and the whole ICMP message, skipping the
+        // header fields the kernel picks itself
+        assert_eq!(reply[9], 1);
+        assert_eq!(reply[12..], expected[12..]);
+        break;
+    }
+}
+
+#[test]
+fn icmp_echo_round_trip_test() {
+    as_root("icmp_echo_round_trip_test", icmp_echo_round_trip);
+}
@@ -0,0 +1,17 @@
+extern crate tuntap;
+
+use tuntap::{detect_ip_version, IpVersion};
+
+#[test]
+fn version_nibble() {
+    assert_eq!(detect_ip_version(&[0x45, 0, 0, 20]), Some(IpVersion::V4));
+    assert_eq!(detect_ip_version(&[0x60, 0, 0, 0]), Some(IpVersion::V6));
+    assert_eq!(detect_ip_version(&[0x50]), None);
+    assert_eq!(detect_ip_version(&[]), None);
+}
+
+#[test]
+fn ethertypes() {
+    assert_eq!(IpVersion::V4.ethertype(), 0x0800);
+    assert_eq!(IpVersion::V6.ethertype(), 0x86dd);
+}